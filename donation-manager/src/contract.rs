@@ -1,36 +1,61 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
-use cosmwasm_std::{Addr, BankMsg, Binary, Deps, DepsMut, Env, MessageInfo, Order, Reply, Response, StdResult, SubMsg, SubMsgResult, to_binary, Uint128, WasmMsg};
-// use cw2::set_contract_version;
+use cosmwasm_std::{Addr, Binary, Coin, Decimal, Decimal256, Deps, DepsMut, Env, from_json, instantiate2_address, MessageInfo, Order, Reply, Response, StdResult, Storage, SubMsg, Timestamp, to_json_binary, Uint128, WasmMsg};
+use cw_storage_plus::Bound;
+use cw_utils::{must_pay, nonpayable, one_coin, parse_reply_instantiate_data};
+use cw2::set_contract_version;
+use cw20::Cw20ReceiveMsg;
+use donation_peer::helpers::{cw20_denom, cw20_token, migrating_from, send_coins};
+use donation_peer::msg::DonatorsResponse;
+use donation_peer::state::{Decay, DenomPolicy};
+use semver::Version;
+use sha2::{Digest, Sha256};
 
 use crate::error::ContractError;
-use crate::msg::{ExecuteMsg, GetConfigResponse, InstantiateMsg, MemberPeerAddrResp, QueryMsg};
-use crate::state::{Config, CONFIG, MEMBERS, PENDING_INSTANTIATION};
+use crate::helpers::{add_coin, capped_split};
+use crate::rewards;
+use crate::msg::{AdminResp, ConfigUpdate, EpochPayout, EpochResp, ExecuteMsg, GetConfigResponse, InstantiateMsg, MemberCountResp, MemberInfo, MemberPeerAddrResp, MembersResp, MigrateMsg, PendingRewardsResp, PredictPeerAddrResp, QueryMsg, ReceiveMsg, RoundMatchResp, RoundResp};
+use crate::state::{ADMIN, Config, CONFIG, CONTRIBUTIONS, CURRENT_EPOCH, Distribution, DISTRIBUTION, Epoch, EPOCH_PAYOUTS, EPOCHS, HELD, LEGACY_CONFIG, LEGACY_MEMBERS, LEGACY_PENDING_INSTANTIATION, MATCHES, Member, MEMBER_COUNT, members, NEXT_DISTRIBUTION, NEXT_REPLY_ID, peer_of, PEER_SALT_NONCE, PENDING_ADMIN, PENDING_INSTANTIATION, Resettle, RESETTLE, Round, ROUND, ROUND_COUNT, ROUND_END, RoundEnd, SQRT_SUMS, TOTAL_SHARES, WeightStrategy, WEIGHTS, ZeroWeightPolicy};
 
-/*
 const CONTRACT_NAME: &str = "crates.io:donation-manager";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
- */
 
-// Used to identify the Response-Submessages
-pub const PEER_INSTANTIATE_ID: u64 = 1;
+// Pagination of the members list
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
+// The capped strategy looks at every member on every donation, so their number is bounded
+const MAX_CAPPED_MEMBERS: u64 = 100;
+
 
 // ////////////////////////////////////////INSTANTIATE///////////////////////////////////////////////
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     deps: DepsMut,
     _env: Env,
-    _info: MessageInfo,
+    info: MessageInfo,
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
 
     let config = Config{
         peer_code_id: msg.peer_code_id,
         incremental_donation: msg.incremental_donation,
-        collective_ratio: msg.collective_ratio
+        collective_ratio: msg.collective_ratio,
+        other_denoms: msg.other_denoms,
+        zero_weight: msg.zero_weight,
+        predictable_addresses: msg.predictable_addresses,
+        weight_strategy: msg.weight_strategy,
+        floor_share: msg.floor_share,
+        epoch_length: msg.epoch_length,
+        decay: msg.decay,
+        donor_share: msg.donor_share,
     };
+    validate_config(deps.as_ref(), &config)?;
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
     CONFIG.save(deps.storage,&config)?;
+    ADMIN.save(deps.storage, &Some(info.sender))?;
+    MEMBER_COUNT.save(deps.storage, &0)?;
+    TOTAL_SHARES.save(deps.storage, &Uint128::zero())?;
     Ok(Response::new().add_attribute("action","manager-instantiated"))
 
 }
@@ -44,361 +69,3141 @@ pub fn execute(
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::Join { .. } => {join(deps, info)},
-        ExecuteMsg::Leave { .. } => {Ok(Response::new())},
-        ExecuteMsg::Donate { .. } => {donate(deps, env, info)},
+        ExecuteMsg::Join { .. } => {join(deps, env, info)},
+        ExecuteMsg::Leave { .. } => {leave(deps, env, info)},
+        ExecuteMsg::Donate { .. } => {
+            // Native denoms cannot pose as CW20 tokens
+            if let Some(coin) = info.funds.iter().find(|coin| cw20_token(&coin.denom).is_some()) {
+                return Err(ContractError::InvalidDenom { denom: coin.denom.clone() });
+            }
+            donate(deps, env, info.sender, info.funds)
+        },
+        ExecuteMsg::Receive(msg) => {receive(deps, env, info, msg)},
+        ExecuteMsg::Claim {} => {claim(deps, info)},
+        ExecuteMsg::UpdateWeight { weight, unique_donors } => {update_weight(deps, env, info, weight, unique_donors)},
+        ExecuteMsg::RecordDonation { donor, funds } => {record_donation(deps, env, info, donor, funds)},
+        ExecuteMsg::StartRound { end } => {start_round(deps, env, info, end)},
+        ExecuteMsg::FundRound {} => {fund_round(deps, env, info)},
+        ExecuteMsg::EndRound { limit } => {end_round(deps, env, info, limit)},
+        ExecuteMsg::Distribute { limit } => {distribute_epoch(deps, env, info, limit)},
+        ExecuteMsg::ProposeAdmin { admin } => {propose_admin(deps, info, admin)},
+        ExecuteMsg::AcceptAdmin {} => {accept_admin(deps, info)},
+        ExecuteMsg::RenounceAdmin {} => {renounce_admin(deps, info)},
+        ExecuteMsg::UpdateConfig(update) => {update_config(deps, env, info, *update)},
+        ExecuteMsg::Resettle { limit } => {resettle(deps, env, info, limit)},
+        ExecuteMsg::SyncWeights { start_after, limit } => {sync_weights(deps, env, info, start_after, limit)},
+        ExecuteMsg::PropagateConfig { start_after, limit } => {propagate_config(deps, info, start_after, limit)},
+        ExecuteMsg::MigratePeers { new_code_id, msg, start_after, limit } => {
+            migrate_peers(deps, env, info, new_code_id, msg, start_after, limit)
+        },
     }
 
 
 }
 
-fn join (deps:DepsMut, info:MessageInfo) -> Result<Response, ContractError>{
+fn join (deps:DepsMut, env: Env, info:MessageInfo) -> Result<Response, ContractError>{
+    nonpayable(&info)?;
 
     let creator = info.sender.to_string();
     let config = CONFIG.load(deps.storage)?;
 
+    // Step 0: Every creator owns at most one peer, including one which is still being instantiated
+    let is_member = peer_of(deps.storage, &info.sender)?.is_some();
+    let is_pending = PENDING_INSTANTIATION
+        .range(deps.storage, None, None, Order::Ascending)
+        .filter_map(|pending| pending.ok())
+        .any(|(_, owner)| owner == info.sender);
+    if is_member || is_pending {
+        return Err(ContractError::AlreadyMember {});
+    }
+    if let WeightStrategy::CappedLinear { .. } = config.weight_strategy {
+        let pending = PENDING_INSTANTIATION
+            .keys(deps.storage, None, None, Order::Ascending)
+            .count() as u64;
+        if MEMBER_COUNT.load(deps.storage)? + pending >= MAX_CAPPED_MEMBERS {
+            return Err(ContractError::TooManyMembers { max: MAX_CAPPED_MEMBERS });
+        }
+    }
+
     // Step 1: Create instantiate message from called contract
     let msg = donation_peer::msg::InstantiateMsg {
         owner: creator.clone(),
         incremental_donation: config.incremental_donation,
-        collective_ratio: config.collective_ratio
+        collective_ratio: config.collective_ratio,
+        other_denoms: config.other_denoms,
+        decay: config.decay,
     };
 
-    // Step 2: Create a WasmMsg of type instantiate
+    let msg = to_json_binary(&msg)?;
+    let admin = Some(env.contract.address.to_string());
+    let label = format!("{}-peer",creator);
+
+    // With Instantiate2 the peer address is known upfront, so the member is stored right away.
+    // If the instantiation fails the whole transaction is reverted with it
+    if config.predictable_addresses {
+        let salt = peer_salt(deps.as_ref(), &info.sender)?;
+        let peer = predict_peer_addr(deps.as_ref(), &env, config.peer_code_id, &salt)?;
+        PEER_SALT_NONCE.update(deps.storage, &info.sender, |nonce| -> StdResult<_> {
+            Ok(nonce.unwrap_or_default() + 1)
+        })?;
+        add_member(deps.storage, &peer, &info.sender, env.block.height)?;
+
+        let msg = WasmMsg::Instantiate2 {
+            admin,
+            code_id: config.peer_code_id,
+            label,
+            msg,
+            funds: vec![],
+            salt,
+        };
+
+        let resp = Response::new()
+            .add_message(msg)
+            .add_attribute("action","join")
+            .add_attribute("creator",info.sender.to_string())
+            .add_attribute("peer", peer);
+        return Ok(resp);
+    }
+
+    // Step 2: Create a WasmMsg of type instantiate. The manager is the wasm admin, so it can migrate its peers
     let msg = WasmMsg::Instantiate {
-        admin: None,
+        admin,
         code_id: config.peer_code_id,
-        msg: to_binary(&msg)?,
+        msg,
         funds: vec![],                  // Also Vec::new()
-        label: format!("{}-peer",creator),
+        label,
     };
 
-    // Step 3: Record the address of the caller/creator of this process
-    PENDING_INSTANTIATION.save(deps.storage,&info.sender)?;
+    // Step 3: Record the address of the caller/creator of this process under a fresh reply id,
+    // so the reply is matched with its own creator even if several joins are in flight
+    let reply_id = NEXT_REPLY_ID.may_load(deps.storage)?.unwrap_or_default();
+    NEXT_REPLY_ID.save(deps.storage, &(reply_id + 1))?;
+    PENDING_INSTANTIATION.save(deps.storage, reply_id, &info.sender)?;
 
     // Step 4: Create a response with a submessage attaching the message with reply_on_success
     let resp = Response::new()
-        .add_submessage(SubMsg::reply_on_success(msg,PEER_INSTANTIATE_ID))
+        .add_submessage(SubMsg::reply_on_success(msg, reply_id))
         .add_attribute("action","join")
         .add_attribute("creator",info.sender.to_string());
     Ok(resp)
 }
 
-fn donate (deps: DepsMut, env: Env ,info: MessageInfo) -> Result<Response, ContractError> {
+// Salt of the next Instantiate2 peer of `owner`. The nonce makes it change after every peer,
+// so an owner which left can join again
+fn peer_salt(deps: Deps, owner: &Addr) -> StdResult<Binary> {
+    let nonce = PEER_SALT_NONCE.may_load(deps.storage, owner)?.unwrap_or_default();
+    let owner = deps.api.addr_canonicalize(owner.as_str())?;
+    let hash = Sha256::new()
+        .chain_update(owner.as_slice())
+        .chain_update(nonce.to_be_bytes())
+        .finalize();
+    Ok(Binary::from(hash.as_slice()))
+}
 
-    // For each pair of peer/owner on MEMBERS, query the peer contract and get the donators number
-    let weights: Vec<_> = MEMBERS
-        .keys(deps.storage,None,None,Order::Ascending)
-        .map(|peer| -> StdResult<_> {
-            let peer = peer?;
-            let donators = donation_peer::state::STATE.query(&deps.querier,peer.clone())?.donators;
-            Ok((peer,donators as u128))
-        })
-        .collect::<StdResult<_>>()?;   // The tuple provided in the previous step will join a collection (we can iter() and map() it later)
+fn predict_peer_addr(deps: Deps, env: &Env, code_id: u64, salt: &[u8]) -> Result<Addr, ContractError> {
+    let checksum = deps.querier.query_wasm_code_info(code_id)?.checksum;
+    let creator = deps.api.addr_canonicalize(env.contract.address.as_str())?;
+    let addr = instantiate2_address(checksum.as_slice(), &creator, salt)?;
+    Ok(deps.api.addr_humanize(&addr)?)
+}
+
+fn add_member(storage: &mut dyn Storage, peer: &Addr, owner: &Addr, height: u64) -> StdResult<()> {
+    let member = Member {
+        owner: owner.clone(),
+        joined_height: height,
+        weight: 0,
+        units: 0,
+        unique_donors: 0,
+    };
+    members().save(storage, peer.clone(), &member)?;
+    WEIGHTS.save(storage, peer, &0, height)?;
+    // Start accruing rewards from now on
+    rewards::settle(storage, peer, Uint128::zero())?;
+    MEMBER_COUNT.update(storage, |count| -> StdResult<_> { Ok(count + 1) })?;
+    Ok(())
+}
 
-    //From here weights has got a collection of tuples (peer_address, donator as u128)
+fn leave(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
+    nonpayable(&info)?;
 
-    //Add all the donators stored on the weights collection
-    let total_donators : u128 = weights.iter().map(|(_,weight)| weight).sum();
+    // Find the peer owned by the caller
+    let peer = peer_of(deps.storage, &info.sender)?.ok_or(ContractError::NotMember {})?;
+    let member = members().load(deps.storage, peer.clone())?;
+    // Shares of ended epochs are only credited by `Distribute`, the one of the running epoch is forfeited
+    if let Some(id) = undistributed_epoch(deps.storage, &env, &peer)? {
+        return Err(ContractError::UndistributedEpoch { id });
+    }
 
-    // Query the blockchain to obtain this contract's balance on all coins
-    let funds = deps.querier.query_all_balances(env.contract.address)?;
+    // Settle the rewards of the leaving peer and pay them out before its bookkeeping is dropped
+    let shares = shares_of(deps.storage, &peer, &member)?;
+    let settled = rewards::take(deps.storage, &peer, shares)?;
+    rewards::remove(deps.storage, &peer)?;
 
-    // Double loop: for each Peer/Owner -> Peer/weight. Get a list of its share of all coins on the contract
-    //and create an Option which content is a BankMsg::Send with the peer address and his coins
-    let send_msgs = weights.into_iter().filter_map(|(peer, weights)| {
-        //How many coins owned by the contract belong to the peer in analysis
-        let coins: Vec<_>= funds
-            .iter()
-            .cloned()
-            .map (|mut coin| {
-                coin.amount = Uint128::new(coin.amount.u128() * weights / total_donators);
-                coin
-            })
-            .collect();
+    update_total_shares(deps.storage, &peer, shares, Uint128::zero())?;
+    members().remove(deps.storage, peer.clone())?;
+    WEIGHTS.remove(deps.storage, &peer, env.block.height)?;
+    MEMBER_COUNT.update(deps.storage, |count| -> StdResult<_> { Ok(count - 1) })?;
 
-        //If there are coins for this peer contract, not zero.....
-        if coins.iter().all(|c| c.amount==Uint128::zero()) {
-            None
-        } else {
-            Some(BankMsg::Send {
-                to_address: peer.to_string(),
-                amount:coins,
-            })
-            // There is a better way of doing this, sending all in one message or through another contract. See video.
+    // Tell the peer to stop forwarding donations to this manager
+    let detach = WasmMsg::Execute {
+        contract_addr: peer.to_string(),
+        msg: to_json_binary(&donation_peer::msg::ExecuteMsg::Detach {})?,
+        funds: vec![],
+    };
+
+    let mut resp = Response::new();
+    if !settled.is_empty() {
+        resp = resp.add_messages(send_coins(peer.as_str(), settled)?);
+    }
+
+    let resp = resp
+        .add_message(detach)
+        .add_attribute("action", "leave")
+        .add_attribute("owner", info.sender.to_string())
+        .add_attribute("peer", peer);
+
+    Ok(resp)
+}
+
+// The message comes from the token contract. `msg.sender` sent it the tokens, usually a member peer forwarding
+// the collective share of a donation, or anyone donating to the collective directly
+fn receive(deps: DepsMut, env: Env, info: MessageInfo, msg: Cw20ReceiveMsg) -> Result<Response, ContractError> {
+    nonpayable(&info)?;
+    let sender = deps.api.addr_validate(&msg.sender)?;
+
+    match from_json(&msg.msg)? {
+        ReceiveMsg::Donate {} => {
+            let funds = vec![Coin {
+                denom: cw20_denom(info.sender.as_str()),
+                amount: msg.amount,
+            }];
+            donate(deps, env, sender, funds)
         }
-    });
+    }
+}
+
+fn donate (deps: DepsMut, env: Env, sender: Addr, funds: Vec<Coin>) -> Result<Response, ContractError> {
+    let funds: Vec<_> = funds.into_iter().filter(|coin| !coin.amount.is_zero()).collect();
+    if funds.is_empty() {
+        return Err(ContractError::NoFunds {});
+    }
+
+    // Stray tokens are never distributed to the peers. Member peers forward what their own policy accepted,
+    // which may still be the old one until a changed config is propagated, so only direct donations are checked
+    let config = CONFIG.load(deps.storage)?;
+    let main_denom = &config.incremental_donation.denom;
+    if !members().has(deps.storage, sender.clone()) {
+        if let Some(coin) = funds.iter().find(|coin| coin.denom != *main_denom && !config.other_denoms.accepts(&coin.denom)) {
+            return Err(ContractError::InvalidDenom { denom: coin.denom.clone() });
+        }
+    }
+
+    // Peers report their weights on their own, so the cached ones are up to date
+    collect(deps.storage, &env, funds)?;
 
     let resp = Response::new()
-        .add_messages(send_msgs)
         .add_attribute("action", "donate")
-        .add_attribute("sender",info.sender.to_string());
+        .add_attribute("sender",sender.to_string());
 
     Ok(resp)
 }
 
+fn claim(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    nonpayable(&info)?;
 
-// ////////////////////////////////////////QUERY////////////////////////////////////////////////////
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::Config { .. } => query_config(deps),
-        QueryMsg::MemberPeerAddr {addr} => to_binary(&query_member_peer_addr(deps, &addr)?)
+    // Rewards can be claimed by the peer itself or by its owner, they are always sent to the peer
+    let peer = if members().has(deps.storage, info.sender.clone()) {
+        info.sender.clone()
+    } else {
+        peer_of(deps.storage, &info.sender)?.ok_or(ContractError::NotMember {})?
+    };
+    let member = members().load(deps.storage, peer.clone())?;
+    let shares = shares_of(deps.storage, &peer, &member)?;
+
+    let rewards = rewards::take(deps.storage, &peer, shares)?;
+    if rewards.is_empty() {
+        return Err(ContractError::NothingToClaim {});
     }
+
+    let resp = Response::new()
+        .add_messages(send_coins(peer.as_str(), rewards)?)
+        .add_attribute("action", "claim")
+        .add_attribute("peer", peer);
+
+    Ok(resp)
 }
 
+fn update_weight(deps: DepsMut, env: Env, info: MessageInfo, units: u64, unique_donors: u64) -> Result<Response, ContractError> {
+    let peer = info.sender;
+    let member = members()
+        .may_load(deps.storage, peer.clone())?
+        .ok_or(ContractError::Unauthorized {})?;
+    let weight = set_weight(deps.storage, &env, &peer, member, units, unique_donors)?;
+
+    let resp = Response::new()
+        .add_attribute("action", "update_weight")
+        .add_attribute("peer", peer)
+        .add_attribute("weight", weight.to_string());
 
-pub fn query_config(deps: Deps) -> StdResult<Binary> {
-// when using may_load the response of the function seems to need-be an Option
-    let config = CONFIG.may_load(deps.storage)?;
-    let resp = to_binary(&GetConfigResponse{ config })?;
     Ok(resp)
 }
 
-pub fn query_member_peer_addr(deps: Deps, addr: &str) -> StdResult<MemberPeerAddrResp> {
-    // Find all the peers whose owner is the addr parameter.
-    // We search in the MEMBERS storage unit. We do not ask outside this contract.
-    let peer = MEMBERS
-        .range(deps.storage, None, None, Order::Ascending)
-        .filter_map(|addr| addr.ok())           //The returned iterator yields only the values for which the supplied closure returns Some(value).
-        .find(|(_, owner)| owner.as_str() == addr);
+// Stores the numbers reported by the peer and the weight blended from them, returning the weight
+fn set_weight(storage: &mut dyn Storage, env: &Env, peer: &Addr, mut member: Member, units: u64, unique_donors: u64) -> StdResult<u64> {
+    let config = CONFIG.load(storage)?;
+    member.units = units;
+    member.unique_donors = unique_donors;
+    let weight = member.blended_weight(config.donor_share);
 
-    // let (peer, _) = peer.ok_or_else(|| StdError::generic_err("No such member"))?;
+    // An epoch which starts now must not see the new weight, so it is opened before the weight changes
+    current_epoch(storage, env)?;
+    WEIGHTS.save(storage, peer, &weight, env.block.height)?;
 
-    // NEXT LINE DOES NOT WORK with ? because ContractErrror::CustomError does not implement follow something.
-    // ok_or_else Transforms the Option<T> into a Result<T, E>, mapping Some(v) to Ok(v) and None to Err(err()).
-    let (peer, _) = peer.ok_or_else(|| ContractError::CustomError { val: "No such member".to_string() }).unwrap();
+    // Settle with the old weight first, so the past rewards of the peer are not affected
+    let old_shares = shares_of(storage, peer, &member)?;
+    rewards::settle(storage, peer, old_shares)?;
+    update_total_shares(storage, peer, old_shares, config.weight_strategy.shares(weight))?;
+    member.weight = weight;
+    members().save(storage, peer.clone(), &member)?;
+    Ok(weight)
+}
 
-    Ok(MemberPeerAddrResp{ addr: peer })
+fn sync_weights(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    nonpayable(&info)?;
+
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after
+        .map(|peer| deps.api.addr_validate(&peer))
+        .transpose()?
+        .map(Bound::exclusive);
+    let page = members()
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let mut synced = 0u64;
+    for (peer, member) in &page {
+        let current: DonatorsResponse = deps
+            .querier
+            .query_wasm_smart(peer, &donation_peer::msg::QueryMsg::Donators {})?;
+        if current.weight != member.units || current.unique_donors != member.unique_donors {
+            set_weight(deps.storage, &env, peer, member.clone(), current.weight, current.unique_donors)?;
+            synced += 1;
+        }
+    }
+
+    // The last peer visited is where the next page starts
+    let mut resp = Response::new()
+        .add_attribute("action", "sync_weights")
+        .add_attribute("synced", synced.to_string());
+    if let Some((last, _)) = page.last() {
+        resp = resp.add_attribute("last", last);
+    }
+
+    Ok(resp)
 }
 
-// ////////////////////////////////////////REPLY////////////////////////////////////////////////////
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response,ContractError> {
-    match msg.id {
-        PEER_INSTANTIATE_ID => {peer_instantiate_reply(deps, msg.result)},
-        _ => Err(ContractError::CustomError {val:"unknown reply id".to_string()})
+// Shares the member holds right now. While members are moved to new shares, the ones which were not
+// moved yet keep their old shares
+fn shares_of(storage: &dyn Storage, peer: &Addr, member: &Member) -> StdResult<Uint128> {
+    let strategy = match RESETTLE.may_load(storage)? {
+        Some(resettle) if !is_moved(&resettle, peer) => resettle.old_strategy,
+        _ => CONFIG.load(storage)?.weight_strategy,
+    };
+    Ok(strategy.shares(member.weight))
+}
+
+fn is_moved(resettle: &Resettle, peer: &Addr) -> bool {
+    resettle.last.as_ref().is_some_and(|last| peer <= last)
+}
+
+// Replaces `old` shares of the member with `new` ones in the total. Members which were not moved to new
+// shares yet are only added to the total once they are
+fn update_total_shares(storage: &mut dyn Storage, peer: &Addr, old: Uint128, new: Uint128) -> StdResult<()> {
+    match RESETTLE.may_load(storage)? {
+        Some(mut resettle) => {
+            if is_moved(&resettle, peer) {
+                resettle.total_shares = resettle.total_shares - old + new;
+                RESETTLE.save(storage, &resettle)?;
+            }
+            Ok(())
+        }
+        None => {
+            TOTAL_SHARES.update(storage, |total| -> StdResult<_> { Ok(total - old + new) })?;
+            Ok(())
+        }
     }
 }
 
-fn peer_instantiate_reply (deps: DepsMut, msg: SubMsgResult) -> Result<Response,ContractError> {
-    //Objetive: Access the address of the newly created contract to save it @ Members
-    // Three steps: check submsg response, get its data, parse its data.
-    //Then we can access the resp.contract_address that is the address of the newly generated contract. We can update MEMBERS then
+fn ensure_admin(deps: Deps, sender: &Addr) -> Result<(), ContractError> {
+    match ADMIN.load(deps.storage)? {
+        Some(admin) if admin == *sender => Ok(()),
+        _ => Err(ContractError::Unauthorized {}),
+    }
+}
 
-    // 1.- Make sure you have got the response Ok and not an Err. Next line does not work because msg.into_result does not produce the right error.
-    //let resp = msg.into_result()?;
+fn validate_config(deps: Deps, config: &Config) -> Result<(), ContractError> {
+    if config.collective_ratio > Decimal::one() {
+        return Err(ContractError::InvalidRatio {});
+    }
 
-    // Alternative to ? when getting the result of a function. Here for some reason on the workshop we can not use ?
-    let resp = match msg.into_result() {
-        Ok(resp ) => resp,
-        Err(err) => {return Err(ContractError::CustomError {val: err.to_string()})}
+    let invalid_cap = match config.weight_strategy {
+        WeightStrategy::CappedLinear { max_share } => max_share.is_zero() || max_share > Decimal::one(),
+        _ => false,
     };
+    if config.floor_share > Decimal::one() || config.donor_share > Decimal::one() || invalid_cap {
+        return Err(ContractError::InvalidShare {});
+    }
+    if !config.decay.is_valid() {
+        return Err(ContractError::InvalidHalfLife {});
+    }
+    if config.decay != Decay::None && !config.donor_share.is_zero() {
+        return Err(ContractError::DonorShareWithDecay {});
+    }
+    if let WeightStrategy::CappedLinear { .. } = config.weight_strategy {
+        if MEMBER_COUNT.may_load(deps.storage)?.unwrap_or_default() > MAX_CAPPED_MEMBERS {
+            return Err(ContractError::TooManyMembers { max: MAX_CAPPED_MEMBERS });
+        }
+    }
 
-     // 2.- Get the data, make sure it is there. In instatiation the data is a Option<Binary>. events can be analized as well.
-    // ok_or_else does nothing if it is ok, but if else, it executes the attached code
-    let data = resp.data
-        .ok_or_else(|| ContractError::CustomError {val:"No instantiate response data".to_string()})?;
+    config.other_denoms.validate_increments(&config.incremental_donation)?;
 
-    // 3.- Parse the data from the Option<Binary>
-    let resp = cw_utils::parse_instantiate_response_data(&data)
-        .map_err(|error| ContractError::CustomError {val:error.to_string()})?;
+    // Fail now rather than on the first Join if the peer code is not there
+    deps.querier
+        .query_wasm_code_info(config.peer_code_id)
+        .map_err(|_| ContractError::UnknownCodeId { code_id: config.peer_code_id })?;
 
-    let creator = PENDING_INSTANTIATION.load(deps.storage)?;
-    //Newly created peer address
-    let peer = Addr::unchecked(resp.contract_address);
+    Ok(())
+}
+
+fn propose_admin(deps: DepsMut, info: MessageInfo, admin: String) -> Result<Response, ContractError> {
+    ensure_admin(deps.as_ref(), &info.sender)?;
 
-    MEMBERS.save(deps.storage, peer.clone(), &creator.clone())?;
+    let admin = deps.api.addr_validate(&admin)?;
+    PENDING_ADMIN.save(deps.storage, &admin)?;
 
     let resp = Response::new()
-        .add_attribute("action","joined")
-        .add_attribute("owner", creator)
-        .add_attribute("peer",peer);
+        .add_attribute("action", "propose_admin")
+        .add_attribute("admin", admin);
 
     Ok(resp)
 }
 
-// ////////////////////////////////////////TESTS/////////////////////////////////////////////////////
-#[cfg(test)]
-mod tests {
-    use cosmwasm_std::{Addr, coin, Decimal, Empty};
-    use cw_multi_test::{App, Contract, ContractWrapper, Executor};
-    use donation_peer::msg::{DonatorsResponse, ManagerResp, OwnerResp};
-    use crate::contract::{execute, instantiate, query, reply};
-    use crate::msg::{ExecuteMsg, GetConfigResponse, InstantiateMsg, MemberPeerAddrResp, QueryMsg};
-    use crate::state::Config;
+fn accept_admin(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    match PENDING_ADMIN.may_load(deps.storage)? {
+        Some(pending) if pending == info.sender => (),
+        _ => return Err(ContractError::Unauthorized {}),
+    }
 
-    fn peer() -> Box<dyn Contract<Empty>> {
-        let contract = ContractWrapper::new(donation_peer::contract::execute, donation_peer::contract::instantiate, donation_peer::contract::query);
-        Box::new(contract)
+    PENDING_ADMIN.remove(deps.storage);
+    ADMIN.save(deps.storage, &Some(info.sender.clone()))?;
+
+    let resp = Response::new()
+        .add_attribute("action", "accept_admin")
+        .add_attribute("admin", info.sender);
+
+    Ok(resp)
+}
+
+fn renounce_admin(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    ensure_admin(deps.as_ref(), &info.sender)?;
+
+    PENDING_ADMIN.remove(deps.storage);
+    ADMIN.save(deps.storage, &None)?;
+
+    Ok(Response::new().add_attribute("action", "renounce_admin"))
+}
+
+fn update_config(deps: DepsMut, env: Env, info: MessageInfo, update: ConfigUpdate) -> Result<Response, ContractError> {
+    ensure_admin(deps.as_ref(), &info.sender)?;
+
+    let old = CONFIG.load(deps.storage)?;
+    let config = Config {
+        peer_code_id: update.peer_code_id.unwrap_or(old.peer_code_id),
+        incremental_donation: update.incremental_donation.unwrap_or_else(|| old.incremental_donation.clone()),
+        collective_ratio: update.collective_ratio.unwrap_or(old.collective_ratio),
+        other_denoms: update.other_denoms.unwrap_or_else(|| old.other_denoms.clone()),
+        zero_weight: update.zero_weight.unwrap_or(old.zero_weight),
+        predictable_addresses: update.predictable_addresses.unwrap_or(old.predictable_addresses),
+        weight_strategy: update.weight_strategy.unwrap_or_else(|| old.weight_strategy.clone()),
+        floor_share: update.floor_share.unwrap_or(old.floor_share),
+        epoch_length: update.epoch_length.unwrap_or(old.epoch_length),
+        decay: update.decay.unwrap_or_else(|| old.decay.clone()),
+        donor_share: update.donor_share.unwrap_or(old.donor_share),
+    };
+    validate_config(deps.as_ref(), &config)?;
+
+    let mut resp = Response::new().add_attribute("action", "update_config");
+
+    // Shares depend on the strategy and weights on the donor share, so the members are moved to new shares.
+    // There may be too many of them for one transaction, the first page is moved here and `Resettle` goes on
+    if old.weight_strategy != config.weight_strategy || old.donor_share != config.donor_share {
+        if RESETTLE.may_load(deps.storage)?.is_some() {
+            return Err(ContractError::ResettleRunning {});
+        }
+        // A running epoch keeps the weights it started with
+        current_epoch(deps.storage, &env)?;
+        CONFIG.save(deps.storage, &config)?;
+
+        RESETTLE.save(deps.storage, &Resettle { old_strategy: old.weight_strategy, last: None, total_shares: Uint128::zero() })?;
+        let (moved, done) = resettle_page(deps.storage, &env, DEFAULT_LIMIT as usize)?;
+        resp = resp
+            .add_attribute("moved", moved.to_string())
+            .add_attribute("done", done.to_string());
+    } else {
+        CONFIG.save(deps.storage, &config)?;
     }
 
-    fn manager() -> Box<dyn Contract<Empty>> {
-        let contract = ContractWrapper::new(execute, instantiate, query).with_reply(reply);
-        Box::new(contract)
+    Ok(resp)
+}
+
+fn resettle(deps: DepsMut, env: Env, info: MessageInfo, limit: Option<u32>) -> Result<Response, ContractError> {
+    nonpayable(&info)?;
+    if RESETTLE.may_load(deps.storage)?.is_none() {
+        return Err(ContractError::NoResettle {});
     }
 
-    #[test]
-    fn instantiate_check() {
-        let mut app = App::default();
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let (moved, done) = resettle_page(deps.storage, &env, limit)?;
 
-        let peer_code_id = app.store_code(peer());
-        let manager_code_id = app.store_code(manager());
+    let resp = Response::new()
+        .add_attribute("action", "resettle")
+        .add_attribute("moved", moved.to_string())
+        .add_attribute("done", done.to_string());
+    Ok(resp)
+}
 
-        let manager_addr = app
-            .instantiate_contract(
-                manager_code_id,
-                Addr::unchecked("manager_creator_address"),
-                &InstantiateMsg{
-                    peer_code_id,
-                    incremental_donation: coin(100, "utdg"),
-                    collective_ratio: Decimal::percent(60),
-                },
-            &[],
-            "manager",
-            None)
-            .unwrap();
+// Settles the next `limit` members with their old shares and gives them new ones. Once every member is moved
+// the new total is in place, and the held donations go out with the next distribution.
+// Returns the number of members moved and whether they were the last ones.
+fn resettle_page(storage: &mut dyn Storage, env: &Env, limit: usize) -> StdResult<(usize, bool)> {
+    let mut resettle = RESETTLE.load(storage)?;
+    let config = CONFIG.load(storage)?;
+    current_epoch(storage, env)?;
 
-        let config : GetConfigResponse= app
-            .wrap()
-            .query_wasm_smart(manager_addr.clone(), &QueryMsg::Config {})
-            .unwrap();
+    let start = resettle.last.clone().map(Bound::exclusive);
+    let page = members()
+        .range(storage, start, None, Order::Ascending)
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
 
-        assert_eq!(config, GetConfigResponse{ config : Some(Config{
-            peer_code_id,
-            incremental_donation: coin(100,"utdg"),
-            collective_ratio: Decimal::percent(60),
-        }) } )
+    for (peer, member) in &page {
+        rewards::settle(storage, peer, resettle.old_strategy.shares(member.weight))?;
+        let weight = member.blended_weight(config.donor_share);
+        if weight != member.weight {
+            members().save(storage, peer.clone(), &Member { weight, ..member.clone() })?;
+            WEIGHTS.save(storage, peer, &weight, env.block.height)?;
+        }
+        resettle.total_shares += config.weight_strategy.shares(weight);
     }
 
-    #[test]
-    fn join_check() {
-        let mut app = App::default();
-        let peer_code_id = app.store_code(peer());
-        let manager_code_id = app.store_code(manager());
+    let done = page.len() < limit;
+    if done {
+        TOTAL_SHARES.save(storage, &resettle.total_shares)?;
+        RESETTLE.remove(storage);
+    } else {
+        resettle.last = page.last().map(|(peer, _)| peer.clone());
+        RESETTLE.save(storage, &resettle)?;
+    }
+    Ok((page.len(), done))
+}
 
-        let manager_address = app
-            .instantiate_contract(
-                manager_code_id,
-                Addr::unchecked("manager_creator_address"),
-                &InstantiateMsg{
-                    peer_code_id,
-                    incremental_donation: coin(100, "utdg"),
-                    collective_ratio: Decimal::percent(60),
-                },
-                &[],
-                "manager",
-                None)
-            .unwrap();
+fn propagate_config(
+    deps: DepsMut,
+    info: MessageInfo,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    ensure_admin(deps.as_ref(), &info.sender)?;
 
-        // Manager - ExecuteMsg::Join {} from "creator_address"
-        app.execute_contract(Addr::unchecked("creator_address"), manager_address.clone(), &ExecuteMsg::Join {}, &[]).unwrap();
+    let config = CONFIG.load(deps.storage)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after
+        .map(|peer| deps.api.addr_validate(&peer))
+        .transpose()?
+        .map(Bound::exclusive);
 
-        // Manager - QueryMsg::MemberPeerAddr { addr: "creator_address".to_string() }
-        let peer : MemberPeerAddrResp = app
-            .wrap()
-            .query_wasm_smart(manager_address.clone(), &QueryMsg::MemberPeerAddr { addr: "creator_address".to_string() })
-            .unwrap();
+    let peers = members()
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
 
-        // Peer - QueryMsg::Owner . The peer address is obtained in the previous step - MemberPeerAddrResp.addr
-        let owner_resp : OwnerResp = app
-            .wrap()
-            .query_wasm_smart(peer.addr.clone(), &donation_peer::msg::QueryMsg::Owner {})
-            .unwrap();
-        assert_eq!(Addr::unchecked("creator_address"),owner_resp.owner);
+    let msg = to_json_binary(&donation_peer::msg::ExecuteMsg::UpdateConfig {
+        incremental_donation: config.incremental_donation,
+        collective_ratio: config.collective_ratio,
+        other_denoms: config.other_denoms,
+        decay: config.decay,
+    })?;
+    let msgs = peers.iter().map(|peer| WasmMsg::Execute {
+        contract_addr: peer.to_string(),
+        msg: msg.clone(),
+        funds: vec![],
+    });
 
-        //Peer - QueryMsg::Manager . It should be the contract that created the peer i.e. the manager
-        let manager_resp : ManagerResp = app
-            .wrap()
-            .query_wasm_smart(peer.addr.clone(),&donation_peer::msg::QueryMsg::Manager {})
-            .unwrap();
-        assert_eq!(manager_address, manager_resp.manager);
+    // The last peer updated is where the next page starts
+    let mut resp = Response::new()
+        .add_messages(msgs)
+        .add_attribute("action", "propagate_config")
+        .add_attribute("count", peers.len().to_string());
+    if let Some(last) = peers.last() {
+        resp = resp.add_attribute("last", last);
+    }
 
-        //Peer - QueryMsg::Donators. It should be zero
-        let donators_resp : DonatorsResponse = app
-            .wrap()
-            .query_wasm_smart(peer.addr,&donation_peer::msg::QueryMsg::Donators {})
-            .unwrap();
-        assert_eq!(donators_resp.donators,0);
+    Ok(resp)
+}
+
+// Peers which joined before the manager became their wasm admin cannot be migrated this way. They are
+// skipped and listed in the `skipped` attribute, so they do not hold back the rest of their page
+fn migrate_peers(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    new_code_id: u64,
+    msg: Binary,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    ensure_admin(deps.as_ref(), &info.sender)?;
+
+    let mut config = CONFIG.load(deps.storage)?;
+    config.peer_code_id = new_code_id;
+    validate_config(deps.as_ref(), &config)?;
+    CONFIG.save(deps.storage, &config)?;
+
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after
+        .map(|peer| deps.api.addr_validate(&peer))
+        .transpose()?
+        .map(Bound::exclusive);
+
+    let peers = members()
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let mut msgs = vec![];
+    let mut skipped = vec![];
+    for peer in &peers {
+        let admin = deps.querier.query_wasm_contract_info(peer)?.admin;
+        if admin.as_deref() == Some(env.contract.address.as_str()) {
+            msgs.push(WasmMsg::Migrate {
+                contract_addr: peer.to_string(),
+                new_code_id,
+                msg: msg.clone(),
+            });
+        } else {
+            skipped.push(peer.to_string());
+        }
     }
 
+    // The last peer of the page is where the next page starts, whether it was migrated or not
+    let mut resp = Response::new()
+        .add_attribute("action", "migrate_peers")
+        .add_attribute("code_id", new_code_id.to_string())
+        .add_attribute("count", msgs.len().to_string())
+        .add_messages(msgs);
+    if !skipped.is_empty() {
+        resp = resp.add_attribute("skipped", skipped.join(","));
+    }
+    if let Some(last) = peers.last() {
+        resp = resp.add_attribute("last", last);
+    }
 
-    #[test]
-    fn single_peer_single_donate() {
-        // // After a single donation, the only peer should got the whole amount
-        //
-        // let mut app = App::new(|router, _api, storage| {
-        //     router
-        //         .bank
-        //         .init_balance(storage, &Addr::unchecked("donator"), coins(100, "utgd"))
-        //         .unwrap();
-        // });
-        // let peer_code_id = app.store_code(peer());
-        // let manager_code_id = app.store_code(manager());
-        //
-        // let manager = app
-        //     .instantiate_contract(
-        //         manager_code_id,
-        //         Addr::unchecked("admin"),
-        //         &InstantiateMsg {
-        //             peer_code_id,
-        //             incremental_donation: coin(100, "utgd"),
-        //             collective_ratio: Decimal::percent(60),
-        //         },
-        //         &[],
-        //         "manager",
-        //         None,
-        //     )
-        //     .unwrap();
-        //
-        // app.execute_contract(
-        //     Addr::unchecked("member"),
-        //     manager.clone(),
-        //     &ExecMsg::Join {},
-        //     &[],
-        // )
-        // .unwrap();
-        //
-        // let peer: MemberPeerAddrResp = app
-        //     .wrap()
-        //     .query_wasm_smart(
-        //         manager,
-        //         &QueryMsg::MemberPeerAddr {
-        //             addr: "member".to_owned(),
-        //         },
-        //     )
-        //     .unwrap();
-        //
-        // app.execute_contract(
-        //     Addr::unchecked("donator"),
-        //     peer.addr.clone(),
-        //     &PeerExec::Donate {},
-        //     &coins(100, "utgd"),
-        // )
-        // .unwrap();
-        //
-        // app.execute_contract(
-        //     Addr::unchecked("member"),
-        //     peer.addr.clone(),
-        //     &PeerExec::Withdraw {},
-        //     &[],
-        // )
-        // .unwrap();
-        //
-        // assert_eq!(
-        //     coin(0, "utgd"),
-        //     app.wrap().query_balance("donator", "utgd").unwrap()
-        // );
-        // assert_eq!(
-        //     coin(0, "utgd"),
-        //     app.wrap()
-        //         .query_balance(peer.addr.as_str(), "utgd")
-        //         .unwrap()
-        // );
-        // assert_eq!(
-        //     coin(100, "utgd"),
-        //     app.wrap().query_balance("member", "utgd").unwrap()
-        // );
-    }
-    
+    Ok(resp)
+}
+
+// Only donations in the denom of the round count. The contribution of every donor to a peer adds up
+// during the round, and the sum of the square roots per peer is kept up to date as it goes.
+fn record_donation(deps: DepsMut, env: Env, info: MessageInfo, donor: String, funds: Vec<Coin>) -> Result<Response, ContractError> {
+    nonpayable(&info)?;
+    let peer = info.sender;
+    if !members().has(deps.storage, peer.clone()) {
+        return Err(ContractError::Unauthorized {});
+    }
+    let donor = deps.api.addr_validate(&donor)?;
+
+    let resp = Response::new().add_attribute("action", "record_donation");
+    let round = match ROUND.may_load(deps.storage)? {
+        Some(round) if env.block.time < round.end => round,
+        _ => return Ok(resp),
+    };
+    let amount: Uint128 = funds.iter().filter(|coin| coin.denom == round.denom).map(|coin| coin.amount).sum();
+    if amount.is_zero() {
+        return Ok(resp);
+    }
+
+    let before = CONTRIBUTIONS.may_load(deps.storage, (round.id, &peer, &donor))?.unwrap_or_default();
+    let after = before.checked_add(amount)?;
+    CONTRIBUTIONS.save(deps.storage, (round.id, &peer, &donor), &after)?;
+    SQRT_SUMS.update(deps.storage, (round.id, &peer), |sum| -> StdResult<_> {
+        let sum = sum.unwrap_or_default() + Decimal256::from_ratio(after, 1u8).sqrt();
+        Ok(sum - Decimal256::from_ratio(before, 1u8).sqrt())
+    })?;
+
+    let resp = resp
+        .add_attribute("round", round.id.to_string())
+        .add_attribute("peer", peer)
+        .add_attribute("donor", donor)
+        .add_attribute("amount", amount);
+    Ok(resp)
+}
+
+fn start_round(deps: DepsMut, env: Env, info: MessageInfo, end: Timestamp) -> Result<Response, ContractError> {
+    ensure_admin(deps.as_ref(), &info.sender)?;
+    // A round which is being ended is still running until its matches are paid out
+    if ROUND.may_load(deps.storage)?.is_some() || ROUND_END.may_load(deps.storage)?.is_some() {
+        return Err(ContractError::RoundRunning {});
+    }
+    if end <= env.block.time {
+        return Err(ContractError::InvalidRoundEnd {});
+    }
+
+    // Only native pools, peers report CW20 donations with their `cw20:` denoms which never match them
+    let pool = one_coin(&info)?;
+    let id = ROUND_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    ROUND_COUNT.save(deps.storage, &id)?;
+    ROUND.save(deps.storage, &Round { id, denom: pool.denom.clone(), pool: pool.amount, end })?;
+
+    let resp = Response::new()
+        .add_attribute("action", "start_round")
+        .add_attribute("round", id.to_string())
+        .add_attribute("pool", pool.to_string())
+        .add_attribute("end", end.to_string());
+    Ok(resp)
+}
+
+fn fund_round(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
+    let mut round = ROUND.may_load(deps.storage)?.ok_or(ContractError::NoRound {})?;
+    if env.block.time >= round.end {
+        return Err(ContractError::RoundOver {});
+    }
+
+    let amount = must_pay(&info, &round.denom)?;
+    round.pool = round.pool.checked_add(amount)?;
+    ROUND.save(deps.storage, &round)?;
+
+    let resp = Response::new()
+        .add_attribute("action", "fund_round")
+        .add_attribute("round", round.id.to_string())
+        .add_attribute("sponsor", info.sender)
+        .add_attribute("pool", round.pool);
+    Ok(resp)
+}
+
+// The pool is split between the peers proportionally to the square of the sum of the square roots of their
+// contributions, so many small donors weigh more than a single big one. Peers which left during the round
+// get nothing, and without any contribution the pool goes to the members like any other donation.
+// At most `limit` peers are visited, the next call goes on where this one stopped.
+fn end_round(deps: DepsMut, env: Env, info: MessageInfo, limit: Option<u32>) -> Result<Response, ContractError> {
+    nonpayable(&info)?;
+    let mut progress = match ROUND_END.may_load(deps.storage)? {
+        Some(progress) => progress,
+        None => {
+            let round = ROUND.may_load(deps.storage)?.ok_or(ContractError::NoRound {})?;
+            if env.block.time < round.end {
+                return Err(ContractError::RoundNotOver {});
+            }
+            ROUND.remove(deps.storage);
+            RoundEnd { round, last: None, paying: false, total_weight: Uint128::zero(), paid: Uint128::zero(), peers: 0 }
+        }
+    };
+    let id = progress.round.id;
+
+    let mut budget = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let mut done = false;
+    while budget > 0 && !done {
+        let start = progress.last.as_ref().map(Bound::exclusive);
+        let page = SQRT_SUMS
+            .prefix(id)
+            .range(deps.storage, start, None, Order::Ascending)
+            .take(budget)
+            .collect::<StdResult<Vec<_>>>()?;
+        let last_page = page.len() < budget;
+        budget -= page.len();
+
+        for (peer, sum) in &page {
+            if !members().has(deps.storage, peer.clone()) {
+                continue;
+            }
+            let weight = quadratic_weight(*sum)?;
+            if !progress.paying {
+                progress.total_weight = progress.total_weight.checked_add(weight)?;
+                continue;
+            }
+
+            let amount = if progress.total_weight.is_zero() {
+                Uint128::zero()
+            } else {
+                progress.round.pool.multiply_ratio(weight, progress.total_weight)
+            };
+            MATCHES.save(deps.storage, (id, peer), &amount)?;
+            if !amount.is_zero() {
+                rewards::credit(deps.storage, peer, &Coin { denom: progress.round.denom.clone(), amount })?;
+                progress.paid += amount;
+            }
+            progress.peers += 1;
+        }
+
+        progress.last = page.last().map(|(peer, _)| peer.clone());
+        if last_page {
+            progress.last = None;
+            done = progress.paying;
+            progress.paying = true;
+        }
+    }
+
+    let mut resp = Response::new()
+        .add_attribute("action", "end_round")
+        .add_attribute("round", id.to_string())
+        .add_attribute("pool", progress.round.pool)
+        .add_attribute("peers", progress.peers.to_string())
+        .add_attribute("done", done.to_string());
+    if done {
+        // The whole pool without any contribution, or what is left by rounding
+        let left = progress.round.pool.checked_sub(progress.paid)?;
+        if !left.is_zero() {
+            collect(deps.storage, &env, vec![Coin { denom: progress.round.denom, amount: left }])?;
+        }
+        ROUND_END.remove(deps.storage);
+    } else {
+        if let Some(last) = &progress.last {
+            resp = resp.add_attribute("last", last);
+        }
+        ROUND_END.save(deps.storage, &progress)?;
+    }
+    Ok(resp)
+}
+
+// Square of the sum of the square roots of the contributions to a peer, without the fraction
+fn quadratic_weight(sqrt_sum: Decimal256) -> StdResult<Uint128> {
+    Ok(Uint128::try_from(sqrt_sum.checked_pow(2)?.to_uint_floor())?)
+}
+
+// Epoch collecting the donations right now, None without epochs. Once the last epoch is over the next one
+// is opened here, so it starts with the weights at the beginning of the block of its first donation or weight update
+fn current_epoch(storage: &mut dyn Storage, env: &Env) -> StdResult<Option<u64>> {
+    let last = CURRENT_EPOCH.may_load(storage)?;
+    if let Some(id) = last {
+        if env.block.time < EPOCHS.load(storage, id)?.end {
+            return Ok(Some(id));
+        }
+    }
+
+    let config = CONFIG.load(storage)?;
+    if config.epoch_length == 0 {
+        return Ok(None);
+    }
+    let id = last.unwrap_or_default() + 1;
+    let epoch = Epoch {
+        start: env.block.time,
+        end: env.block.time.plus_seconds(config.epoch_length),
+        start_height: env.block.height,
+        funds: vec![],
+        distributed: false,
+        weight_strategy: config.weight_strategy,
+        floor_share: config.floor_share,
+        zero_weight: config.zero_weight,
+    };
+    EPOCHS.save(storage, id, &epoch)?;
+    CURRENT_EPOCH.save(storage, &id)?;
+    Ok(Some(id))
+}
+
+// Adds donated funds to the running epoch, or distributes them right away without epochs
+fn collect(storage: &mut dyn Storage, env: &Env, funds: Vec<Coin>) -> Result<(), ContractError> {
+    match current_epoch(storage, env)? {
+        Some(id) => {
+            let mut epoch = EPOCHS.load(storage, id)?;
+            for coin in funds {
+                add_coin(&mut epoch.funds, coin)?;
+            }
+            Ok(EPOCHS.save(storage, id, &epoch)?)
+        }
+        None => distribute(storage, funds),
+    }
+}
+
+// Distributes the oldest epoch which is over, visiting at most `limit` members. Weight updates open epochs
+// as well, so the ones without any donation are skipped on the way.
+fn distribute_epoch(deps: DepsMut, env: Env, info: MessageInfo, limit: Option<u32>) -> Result<Response, ContractError> {
+    nonpayable(&info)?;
+    let mut progress = match DISTRIBUTION.may_load(deps.storage)? {
+        Some(progress) => progress,
+        None => start_distribution(deps.storage, &env)?,
+    };
+    let epoch = EPOCHS.load(deps.storage, progress.epoch)?;
+
+    let mut budget = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let mut done = false;
+    while budget > 0 && !done {
+        let start = progress.last.clone().map(Bound::exclusive);
+        let page = members()
+            .keys(deps.storage, start, None, Order::Ascending)
+            .take(budget)
+            .collect::<StdResult<Vec<_>>>()?;
+        let last_page = page.len() < budget;
+        budget -= page.len();
+
+        if progress.paying {
+            pay_out_page(deps.storage, &mut progress, &epoch, &page)?;
+        } else {
+            for peer in &page {
+                if let Some(weight) = WEIGHTS.may_load_at_height(deps.storage, peer, epoch.start_height)? {
+                    progress.total_shares += epoch_shares(&epoch.weight_strategy, weight);
+                    progress.eligible += 1;
+                }
+            }
+        }
+
+        progress.last = page.last().cloned();
+        if last_page {
+            progress.last = None;
+            done = progress.paying;
+            progress.paying = true;
+        }
+    }
+
+    let mut resp = Response::new()
+        .add_attribute("action", "distribute")
+        .add_attribute("epoch", progress.epoch.to_string())
+        .add_attribute("peers", progress.peers.to_string())
+        .add_attribute("done", done.to_string());
+    if done {
+        finish_distribution(deps.storage, &progress, epoch)?;
+    } else {
+        if let Some(last) = &progress.last {
+            resp = resp.add_attribute("last", last);
+        }
+        DISTRIBUTION.save(deps.storage, &progress)?;
+    }
+    Ok(resp)
+}
+
+// Finds the oldest epoch which is over and has funds to distribute. What was held before goes with it
+fn start_distribution(storage: &mut dyn Storage, env: &Env) -> Result<Distribution, ContractError> {
+    let mut id = NEXT_DISTRIBUTION.may_load(storage)?.unwrap_or(1);
+    loop {
+        let mut epoch = EPOCHS.may_load(storage, id)?.ok_or(ContractError::NoEpoch {})?;
+        if env.block.time < epoch.end {
+            return Err(ContractError::EpochNotOver {});
+        }
+
+        if !epoch.funds.is_empty() {
+            let mut funds = epoch.funds;
+            for coin in take_held(storage)? {
+                add_coin(&mut funds, coin)?;
+            }
+            return Ok(Distribution {
+                epoch: id,
+                funds,
+                last: None,
+                paying: false,
+                total_shares: Uint128::zero(),
+                eligible: 0,
+                paid: vec![],
+                peers: 0,
+            });
+        }
+
+        epoch.distributed = true;
+        EPOCHS.save(storage, id, &epoch)?;
+        NEXT_DISTRIBUTION.save(storage, &(id + 1))?;
+        id += 1;
+    }
+}
+
+// Oldest epoch which is over, still has funds to distribute and took a snapshot of the weight of `peer`
+fn undistributed_epoch(storage: &dyn Storage, env: &Env, peer: &Addr) -> StdResult<Option<u64>> {
+    let mut id = NEXT_DISTRIBUTION.may_load(storage)?.unwrap_or(1);
+    while let Some(epoch) = EPOCHS.may_load(storage, id)? {
+        if env.block.time < epoch.end {
+            break;
+        }
+        if !epoch.funds.is_empty() && WEIGHTS.may_load_at_height(storage, peer, epoch.start_height)?.is_some() {
+            return Ok(Some(id));
+        }
+        id += 1;
+    }
+    Ok(None)
+}
+
+// Shares of a peer in an epoch, where every strategy is split by weight
+fn epoch_shares(strategy: &WeightStrategy, weight: u64) -> Uint128 {
+    match strategy {
+        WeightStrategy::Linear | WeightStrategy::CappedLinear { .. } => Uint128::from(weight),
+        WeightStrategy::Sqrt => strategy.shares(weight),
+        WeightStrategy::Equal => Uint128::one(),
+    }
+}
+
+fn pay_out_page(storage: &mut dyn Storage, progress: &mut Distribution, epoch: &Epoch, page: &[Addr]) -> Result<(), ContractError> {
+    let has_weight = !progress.total_shares.is_zero();
+    let splits: Vec<_> = progress
+        .funds
+        .iter()
+        .map(|coin| split(coin.amount, &epoch.weight_strategy, epoch.floor_share, epoch.zero_weight, has_weight, progress.eligible > 0))
+        .collect();
+
+    // The capped share of a peer depends on the others, the strategy keeps their number bounded
+    let capped = match &epoch.weight_strategy {
+        WeightStrategy::CappedLinear { max_share } => {
+            let mut eligible = vec![];
+            for peer in members().keys(storage, None, None, Order::Ascending).collect::<StdResult<Vec<_>>>()? {
+                if let Some(weight) = WEIGHTS.may_load_at_height(storage, &peer, epoch.start_height)? {
+                    eligible.push((peer, weight as u128));
+                }
+            }
+            let weights: Vec<_> = eligible.iter().map(|(_, weight)| *weight).collect();
+            let mut amounts = vec![];
+            for split in &splits {
+                amounts.push(if split.weighted.is_zero() {
+                    vec![Uint128::zero(); weights.len()]
+                } else {
+                    capped_split(split.weighted, &weights, split.weighted * *max_share)?
+                });
+            }
+            Some((eligible, amounts))
+        }
+        _ => None,
+    };
+
+    for peer in page {
+        let Some(weight) = WEIGHTS.may_load_at_height(storage, peer, epoch.start_height)? else {
+            continue;
+        };
+
+        let mut payout = vec![];
+        for (idx, (coin, split)) in progress.funds.iter().zip(&splits).enumerate() {
+            let weighted = match &capped {
+                Some((eligible, amounts)) => eligible
+                    .binary_search_by(|(other, _)| other.cmp(peer))
+                    .map(|pos| amounts[idx][pos])
+                    .unwrap_or_default(),
+                None if has_weight => split.weighted.multiply_ratio(epoch_shares(&epoch.weight_strategy, weight), progress.total_shares),
+                None => Uint128::zero(),
+            };
+            // A peer with a weight was counted in, so `eligible` is never zero here
+            let amount = weighted + split.equal.multiply_ratio(1u128, progress.eligible.max(1));
+            if !amount.is_zero() {
+                add_coin(&mut payout, Coin { denom: coin.denom.clone(), amount })?;
+            }
+        }
+        if payout.is_empty() {
+            continue;
+        }
+
+        for coin in &payout {
+            rewards::credit(storage, peer, coin)?;
+            add_coin(&mut progress.paid, coin.clone())?;
+        }
+        EPOCH_PAYOUTS.save(storage, (progress.epoch, peer), &payout)?;
+        progress.peers += 1;
+    }
+    Ok(())
+}
+
+// Whatever was not paid out, because it was held, left over by rounding or meant for members who left
+// since, is held for the next distribution
+fn finish_distribution(storage: &mut dyn Storage, progress: &Distribution, mut epoch: Epoch) -> Result<(), ContractError> {
+    for coin in &progress.funds {
+        let paid = progress
+            .paid
+            .iter()
+            .find(|paid| paid.denom == coin.denom)
+            .map(|paid| paid.amount)
+            .unwrap_or_default();
+        let left = coin.amount.checked_sub(paid)?;
+        if !left.is_zero() {
+            HELD.update(storage, &coin.denom, |held| -> StdResult<_> {
+                Ok(held.unwrap_or_default().checked_add(left)?)
+            })?;
+        }
+    }
+
+    epoch.distributed = true;
+    EPOCHS.save(storage, progress.epoch, &epoch)?;
+    NEXT_DISTRIBUTION.save(storage, &(progress.epoch + 1))?;
+    DISTRIBUTION.remove(storage);
+    Ok(())
+}
+
+// Funds held back by earlier distributions, removed from HELD as they are distributed now
+fn take_held(storage: &mut dyn Storage) -> StdResult<Vec<Coin>> {
+    let held = HELD
+        .range(storage, None, None, Order::Ascending)
+        .map(|held| held.map(|(denom, amount)| Coin { denom, amount }))
+        .collect::<StdResult<Vec<_>>>()?;
+    for coin in &held {
+        HELD.remove(storage, &coin.denom);
+    }
+    Ok(held)
+}
+
+// Splits `funds`, together with anything held before, between the members as the weight strategy says.
+// Nothing is sent here, every peer claims its accrued share on its own.
+fn distribute(storage: &mut dyn Storage, funds: Vec<Coin>) -> Result<(), ContractError> {
+    let config = CONFIG.load(storage)?;
+    let total_shares = TOTAL_SHARES.load(storage)?;
+    let member_count = MEMBER_COUNT.load(storage)?;
+
+    // Members are being moved to new shares, the funds wait for them
+    if RESETTLE.may_load(storage)?.is_some() {
+        for coin in funds {
+            HELD.update(storage, &coin.denom, |held| -> StdResult<_> {
+                Ok(held.unwrap_or_default().checked_add(coin.amount)?)
+            })?;
+        }
+        return Ok(());
+    }
+
+    let funds = funds.into_iter().chain(take_held(storage)?);
+    let strategy = &config.weight_strategy;
+
+    // Only the capped strategy looks at every member, as the share of a peer depends on the others
+    let weights = match config.weight_strategy {
+        WeightStrategy::CappedLinear { .. } => members()
+            .range(storage, None, None, Order::Ascending)
+            .map(|member| member.map(|(peer, member)| (peer, member.weight as u128)))
+            .collect::<StdResult<Vec<_>>>()?,
+        _ => vec![],
+    };
+    let has_weight = match config.weight_strategy {
+        WeightStrategy::CappedLinear { .. } => weights.iter().any(|(_, weight)| *weight > 0),
+        WeightStrategy::Equal => member_count > 0,
+        _ => !total_shares.is_zero(),
+    };
+
+    for coin in funds {
+        let split = split(coin.amount, strategy, config.floor_share, config.zero_weight, has_weight, member_count > 0);
+
+        if !split.weighted.is_zero() {
+            match strategy {
+                WeightStrategy::Linear | WeightStrategy::Sqrt => {
+                    rewards::accrue(storage, &Coin { denom: coin.denom.clone(), amount: split.weighted }, total_shares)?;
+                }
+                WeightStrategy::CappedLinear { max_share } => {
+                    let amounts: Vec<_> = weights.iter().map(|(_, weight)| *weight).collect();
+                    let shares = capped_split(split.weighted, &amounts, split.weighted * *max_share)?;
+                    for ((peer, _), amount) in weights.iter().zip(shares) {
+                        if !amount.is_zero() {
+                            rewards::credit(storage, peer, &Coin { denom: coin.denom.clone(), amount })?;
+                        }
+                    }
+                }
+                // Split equally as a whole
+                WeightStrategy::Equal => {}
+            }
+        }
+
+        if !split.equal.is_zero() {
+            rewards::accrue_members(storage, &Coin { denom: coin.denom.clone(), amount: split.equal }, member_count)?;
+        }
+        if !split.hold.is_zero() {
+            HELD.update(storage, &coin.denom, |held| -> StdResult<_> {
+                Ok(held.unwrap_or_default().checked_add(split.hold)?)
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+// Parts of a coin: `equal` goes to every member alike, `weighted` as the weight strategy says and `hold`
+// waits for the next distribution
+struct Split {
+    equal: Uint128,
+    weighted: Uint128,
+    hold: Uint128,
+}
+
+fn split(
+    amount: Uint128,
+    strategy: &WeightStrategy,
+    floor_share: Decimal,
+    zero_weight: ZeroWeightPolicy,
+    has_weight: bool,
+    has_members: bool,
+) -> Split {
+    let mut equal = amount * floor_share;
+    let mut weighted = amount - equal;
+    let mut hold = Uint128::zero();
+
+    // Nobody has weight yet (e.g. only dust donations so far)
+    if !has_weight {
+        match zero_weight {
+            ZeroWeightPolicy::SplitEqually => equal += weighted,
+            ZeroWeightPolicy::Hold => hold += weighted,
+        }
+        weighted = Uint128::zero();
+    }
+    if let WeightStrategy::Equal = strategy {
+        equal += weighted;
+        weighted = Uint128::zero();
+    }
+
+    // Without members there is nobody to split with
+    if !has_members {
+        hold += equal;
+        equal = Uint128::zero();
+    }
+    Split { equal, weighted, hold }
+}
+
+
+// ////////////////////////////////////////QUERY////////////////////////////////////////////////////
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> Result<Binary, ContractError> {
+    match msg {
+        QueryMsg::Config { .. } => Ok(query_config(deps)?),
+        QueryMsg::MemberPeerAddr {addr} => Ok(to_json_binary(&query_member_peer_addr(deps, &addr)?)?),
+        QueryMsg::Members { start_after, limit } => Ok(to_json_binary(&query_members(deps, start_after, limit)?)?),
+        QueryMsg::MemberCount {} => Ok(to_json_binary(&query_member_count(deps)?)?),
+        QueryMsg::PendingRewards { peer } => Ok(to_json_binary(&query_pending_rewards(deps, peer)?)?),
+        QueryMsg::Admin {} => Ok(to_json_binary(&query_admin(deps)?)?),
+        QueryMsg::PredictPeerAddr { owner } => Ok(to_json_binary(&query_predict_peer_addr(deps, env, owner)?)?),
+        QueryMsg::Round {} => Ok(to_json_binary(&RoundResp { round: ROUND.may_load(deps.storage)? })?),
+        QueryMsg::RoundMatch { id, peer } => Ok(to_json_binary(&query_round_match(deps, id, peer)?)?),
+        QueryMsg::Epoch { id, start_after, limit } => Ok(to_json_binary(&query_epoch(deps, id, start_after, limit)?)?),
+    }
+}
+
+
+pub fn query_config(deps: Deps) -> StdResult<Binary> {
+// when using may_load the response of the function seems to need-be an Option
+    let config = CONFIG.may_load(deps.storage)?;
+    let resp = to_json_binary(&GetConfigResponse{ config })?;
+    Ok(resp)
+}
+
+pub fn query_member_peer_addr(deps: Deps, addr: &str) -> Result<MemberPeerAddrResp, ContractError> {
+    // Look the peer up through the owner index of members(). We do not ask outside this contract.
+    let owner = deps.api.addr_validate(addr)?;
+    let peer = peer_of(deps.storage, &owner)?.ok_or(ContractError::NotMember {})?;
+
+    Ok(MemberPeerAddrResp{ addr: peer })
+}
+
+pub fn query_members(deps: Deps, start_after: Option<String>, limit: Option<u32>) -> StdResult<MembersResp> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after
+        .map(|peer| deps.api.addr_validate(&peer))
+        .transpose()?
+        .map(Bound::exclusive);
+
+    let members = members()
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|member| -> StdResult<_> {
+            let (peer, member) = member?;
+            Ok(MemberInfo {
+                peer,
+                owner: member.owner,
+                donators: member.weight,
+                units: member.units,
+                unique_donors: member.unique_donors,
+                joined_height: member.joined_height,
+            })
+        })
+        .collect::<StdResult<_>>()?;
+
+    Ok(MembersResp { members })
+}
+
+pub fn query_member_count(deps: Deps) -> StdResult<MemberCountResp> {
+    let count = MEMBER_COUNT.may_load(deps.storage)?.unwrap_or_default();
+    Ok(MemberCountResp { count })
+}
+
+pub fn query_pending_rewards(deps: Deps, peer: String) -> Result<PendingRewardsResp, ContractError> {
+    let peer = deps.api.addr_validate(&peer)?;
+    let member = members()
+        .may_load(deps.storage, peer.clone())?
+        .ok_or(ContractError::NotMember {})?;
+
+    let shares = shares_of(deps.storage, &peer, &member)?;
+    let rewards = rewards::pending(deps.storage, &peer, shares)?;
+    Ok(PendingRewardsResp { rewards })
+}
+
+pub fn query_admin(deps: Deps) -> StdResult<AdminResp> {
+    Ok(AdminResp {
+        admin: ADMIN.load(deps.storage)?,
+        pending: PENDING_ADMIN.may_load(deps.storage)?,
+    })
+}
+
+pub fn query_predict_peer_addr(deps: Deps, env: Env, owner: String) -> Result<PredictPeerAddrResp, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if !config.predictable_addresses {
+        return Err(ContractError::NotPredictable {});
+    }
+
+    let owner = deps.api.addr_validate(&owner)?;
+    let salt = peer_salt(deps, &owner)?;
+    let addr = predict_peer_addr(deps, &env, config.peer_code_id, &salt)?;
+    Ok(PredictPeerAddrResp { addr })
+}
+
+pub fn query_round_match(deps: Deps, id: u64, peer: String) -> StdResult<RoundMatchResp> {
+    let peer = deps.api.addr_validate(&peer)?;
+    let sqrt_sum = SQRT_SUMS.may_load(deps.storage, (id, &peer))?.unwrap_or_default();
+    Ok(RoundMatchResp {
+        quadratic_weight: quadratic_weight(sqrt_sum)?,
+        matched: MATCHES.may_load(deps.storage, (id, &peer))?,
+    })
+}
+
+pub fn query_epoch(deps: Deps, id: u64, start_after: Option<String>, limit: Option<u32>) -> StdResult<EpochResp> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_after = start_after.map(|peer| deps.api.addr_validate(&peer)).transpose()?;
+    let start = start_after.as_ref().map(Bound::exclusive);
+
+    let epoch = EPOCHS.load(deps.storage, id)?;
+    let payouts = EPOCH_PAYOUTS
+        .prefix(id)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|payout| payout.map(|(peer, amount)| EpochPayout { peer, amount }))
+        .collect::<StdResult<_>>()?;
+    Ok(EpochResp { epoch, payouts })
+}
+
+// ////////////////////////////////////////MIGRATE//////////////////////////////////////////////////
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(mut deps: DepsMut, env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    let stored = migrating_from(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    let mut dropped = vec![];
+    let from = match &stored {
+        Some(version) => version.clone(),
+        None => {
+            dropped = migrate_legacy(deps.branch(), &env)?;
+            Version::new(0, 1, 0)
+        }
+    };
+
+    migrate_state(deps.storage, &env, &from)?;
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    let mut resp = Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("from_version", stored.map_or("legacy".to_string(), |version| version.to_string()))
+        .add_attribute("to_version", CONTRACT_VERSION);
+    if !dropped.is_empty() {
+        resp = resp.add_attribute("dropped", dropped.join(","));
+    }
+
+    Ok(resp)
+}
+
+// Brings a manager deployed before the versions were recorded to the layout of 0.1.0. Its peers could not
+// take donations yet, so the members start without weight. Owners could join more than once back then,
+// only their first peer stays a member and the others are returned
+fn migrate_legacy(deps: DepsMut, env: &Env) -> Result<Vec<String>, ContractError> {
+    let legacy = LEGACY_CONFIG.load(deps.storage)?;
+    let config = Config {
+        peer_code_id: legacy.peer_code_id,
+        incremental_donation: legacy.incremental_donation,
+        collective_ratio: legacy.collective_ratio,
+        // Every denom was split back then
+        other_denoms: DenomPolicy::PassThrough,
+        zero_weight: ZeroWeightPolicy::Hold,
+        predictable_addresses: false,
+        weight_strategy: WeightStrategy::Linear,
+        floor_share: Decimal::zero(),
+        epoch_length: 0,
+        decay: Decay::None,
+        donor_share: Decimal::zero(),
+    };
+    CONFIG.save(deps.storage, &config)?;
+
+    // There was no admin, the wasm admin running this migration takes the role
+    let admin = deps
+        .querier
+        .query_wasm_contract_info(&env.contract.address)?
+        .admin
+        .map(|admin| deps.api.addr_validate(&admin))
+        .transpose()?;
+    ADMIN.save(deps.storage, &admin)?;
+
+    let legacy_members = LEGACY_MEMBERS
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut count = 0;
+    let mut dropped = vec![];
+    for (peer, owner) in legacy_members {
+        // Gone first, the new entry must not find the old one under the same key
+        LEGACY_MEMBERS.remove(deps.storage, &peer);
+        if peer_of(deps.storage, &owner)?.is_some() {
+            dropped.push(peer.to_string());
+            continue;
+        }
+        let member = Member {
+            owner,
+            joined_height: env.block.height,
+            weight: 0,
+            units: 0,
+            unique_donors: 0,
+        };
+        members().save(deps.storage, peer, &member)?;
+        count += 1;
+    }
+    MEMBER_COUNT.save(deps.storage, &count)?;
+    LEGACY_PENDING_INSTANTIATION.remove(deps.storage);
+
+    Ok(dropped)
+}
+
+// Brings the storage layout of `from` up to date, see `migrating_from`
+fn migrate_state(storage: &mut dyn Storage, env: &Env, from: &Version) -> Result<(), ContractError> {
+    // 0.2.0: the total weight became the total shares, which are the weights with the default linear strategy
+    if *from < Version::new(0, 2, 0) {
+        let total = members()
+            .range(storage, None, None, Order::Ascending)
+            .try_fold(Uint128::zero(), |total, member| -> StdResult<_> {
+                Ok(total + Uint128::from(member?.1.weight))
+            })?;
+        TOTAL_SHARES.save(storage, &total)?;
+        storage.remove(b"total_weight");
+    }
+
+    // 0.3.0: weights are snapshotted for the epochs
+    if *from < Version::new(0, 3, 0) {
+        let weights = members()
+            .range(storage, None, None, Order::Ascending)
+            .map(|member| member.map(|(peer, member)| (peer, member.weight)))
+            .collect::<StdResult<Vec<_>>>()?;
+        for (peer, weight) in weights {
+            WEIGHTS.save(storage, &peer, &weight, env.block.height)?;
+        }
+    }
+
+    // 0.4.0: members keep their units apart from their weight, which was made of units only so far
+    if *from < Version::new(0, 4, 0) {
+        let all = members()
+            .range(storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()?;
+        for (peer, mut member) in all {
+            member.units = member.weight;
+            members().save(storage, peer, &member)?;
+        }
+    }
+
+    // 0.5.0: epochs keep the split they were opened with. The ones not distributed yet take the current one
+    if *from < Version::new(0, 5, 0) {
+        let next = NEXT_DISTRIBUTION.may_load(storage)?.unwrap_or(1);
+        let open = EPOCHS
+            .range(storage, Some(Bound::inclusive(next)), None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()?;
+        // Nothing to fill in for a manager which never opened an epoch
+        if !open.is_empty() {
+            let config = CONFIG.load(storage)?;
+            for (id, mut epoch) in open {
+                epoch.weight_strategy = config.weight_strategy.clone();
+                epoch.floor_share = config.floor_share;
+                epoch.zero_weight = config.zero_weight;
+                EPOCHS.save(storage, id, &epoch)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// ////////////////////////////////////////REPLY////////////////////////////////////////////////////
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response,ContractError> {
+    // Every reply id in use belongs to a pending peer instantiation
+    match PENDING_INSTANTIATION.may_load(deps.storage, msg.id)? {
+        Some(creator) => {peer_instantiate_reply(deps, env, creator, msg)},
+        None => Err(ContractError::UnknownReplyId(msg.id))
+    }
+}
+
+fn peer_instantiate_reply (deps: DepsMut, env: Env, creator: Addr, msg: Reply) -> Result<Response,ContractError> {
+    //Objetive: Access the address of the newly created contract to save it @ Members
+    // parse_reply_instantiate_data checks the submsg result, makes sure there is data and parses it.
+    //Then we can access the resp.contract_address that is the address of the newly generated contract. We can update members() then
+    let reply_id = msg.id;
+    let resp = parse_reply_instantiate_data(msg)?;
+
+    PENDING_INSTANTIATION.remove(deps.storage, reply_id);
+    //Newly created peer address
+    let peer = Addr::unchecked(resp.contract_address);
+
+    add_member(deps.storage, &peer, &creator, env.block.height)?;
+
+    let resp = Response::new()
+        .add_attribute("action","joined")
+        .add_attribute("owner", creator)
+        .add_attribute("peer",peer);
+
+    Ok(resp)
+}
+
+// ////////////////////////////////////////TESTS/////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{Addr, Coin, coin, coins, ContractInfoResponse, ContractResult, Decimal, Empty, from_json, Reply, SubMsgResult, SystemResult, to_json_binary, Uint128, WasmMsg, WasmQuery};
+    use cw20::{BalanceResponse, Cw20Coin, Cw20ExecuteMsg, Cw20QueryMsg};
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cw_utils::{Duration, PaymentError};
+    use cw_multi_test::{App, AppBuilder, Contract, ContractWrapper, Executor, next_block, WasmKeeper};
+    use cw_multi_test::addons::{MockAddressGenerator, MockApiBech32};
+    use donation_peer::msg::{DonatorsResponse, ExecuteMsg as PeerExecMsg, ManagerResp, OwnerResp};
+    use donation_peer::helpers::cw20_denom;
+    use donation_peer::state::{Decay, DenomPolicy};
+    use crate::ContractError;
+    use cw2::{get_contract_version, set_contract_version};
+    use crate::contract::{CONTRACT_NAME, CONTRACT_VERSION, execute, instantiate, MAX_CAPPED_MEMBERS, migrate, query, reply};
+    use crate::msg::{AdminResp, ConfigUpdate, EpochPayout, EpochResp, ExecuteMsg, GetConfigResponse, InstantiateMsg, MemberCountResp, MemberPeerAddrResp, MembersResp, MigrateMsg, PendingRewardsResp, PredictPeerAddrResp, QueryMsg, ReceiveMsg, RoundMatchResp, RoundResp};
+    use crate::state::{ADMIN, Config, CONFIG, HELD, LEGACY_CONFIG, LEGACY_MEMBERS, LEGACY_PENDING_INSTANTIATION, LegacyConfig, MEMBER_COUNT, members, peer_of, TOTAL_SHARES, WeightStrategy, ZeroWeightPolicy};
+
+    fn peer() -> Box<dyn Contract<Empty>> {
+        let contract = ContractWrapper::new(donation_peer::contract::execute, donation_peer::contract::instantiate, donation_peer::contract::query)
+            .with_migrate(donation_peer::contract::migrate);
+        Box::new(contract)
+    }
+
+    fn manager() -> Box<dyn Contract<Empty>> {
+        let contract = ContractWrapper::new(execute, instantiate, query).with_reply(reply);
+        Box::new(contract)
+    }
+
+    // The 100utgd/60% setup used across the tests
+    fn instantiate_msg(peer_code_id: u64) -> InstantiateMsg {
+        InstantiateMsg {
+            peer_code_id,
+            incremental_donation: coin(100, "utgd"),
+            collective_ratio: Decimal::percent(60),
+            other_denoms: DenomPolicy::PassThrough,
+            zero_weight: ZeroWeightPolicy::Hold,
+            predictable_addresses: false,
+            weight_strategy: WeightStrategy::Linear,
+            floor_share: Decimal::zero(),
+            epoch_length: 0,
+            decay: Decay::None,
+            donor_share: Decimal::zero(),
+        }
+    }
+
+    // Stores both contracts and instantiates the manager with the setup used across the tests
+    fn setup_manager(app: &mut App) -> Addr {
+        setup_manager_with(app, |msg| msg)
+    }
+
+    // Like `setup_manager`, with the changes `update` makes to the message
+    fn setup_manager_with(app: &mut App, update: impl FnOnce(InstantiateMsg) -> InstantiateMsg) -> Addr {
+        let peer_code_id = app.store_code(peer());
+        let manager_code_id = app.store_code(manager());
+
+        app.instantiate_contract(manager_code_id, Addr::unchecked("admin"), &update(instantiate_msg(peer_code_id)), &[], "manager", None)
+            .unwrap()
+    }
+
+    // Joins the collective as `owner` and returns the address of the created peer
+    fn join_as(app: &mut App, manager: &Addr, owner: &str) -> Addr {
+        app.execute_contract(Addr::unchecked(owner), manager.clone(), &ExecuteMsg::Join {}, &[])
+            .unwrap();
+
+        let peer: MemberPeerAddrResp = app
+            .wrap()
+            .query_wasm_smart(manager, &QueryMsg::MemberPeerAddr { addr: owner.to_owned() })
+            .unwrap();
+        peer.addr
+    }
+
+    #[test]
+    fn instantiate_check() {
+        let mut app = App::default();
+
+        let peer_code_id = app.store_code(peer());
+        let manager_code_id = app.store_code(manager());
+
+        let manager_addr = app
+            .instantiate_contract(
+                manager_code_id,
+                Addr::unchecked("manager_creator_address"),
+                &InstantiateMsg{
+                    peer_code_id,
+                    incremental_donation: coin(100, "utdg"),
+                    collective_ratio: Decimal::percent(60),
+                    other_denoms: DenomPolicy::PassThrough,
+                    zero_weight: ZeroWeightPolicy::Hold,
+                    predictable_addresses: false,
+                    weight_strategy: WeightStrategy::Linear,
+                    floor_share: Decimal::zero(),
+                    epoch_length: 0,
+                    decay: Decay::None,
+                    donor_share: Decimal::zero(),
+                },
+            &[],
+            "manager",
+            None)
+            .unwrap();
+
+        let config : GetConfigResponse= app
+            .wrap()
+            .query_wasm_smart(manager_addr.clone(), &QueryMsg::Config {})
+            .unwrap();
+
+        assert_eq!(config, GetConfigResponse{ config : Some(Config{
+            peer_code_id,
+            incremental_donation: coin(100,"utdg"),
+            collective_ratio: Decimal::percent(60),
+            other_denoms: DenomPolicy::PassThrough,
+            zero_weight: ZeroWeightPolicy::Hold,
+            predictable_addresses: false,
+            weight_strategy: WeightStrategy::Linear,
+            floor_share: Decimal::zero(),
+            epoch_length: 0,
+            decay: Decay::None,
+            donor_share: Decimal::zero(),
+        }) } )
+    }
+
+    #[test]
+    fn join_check() {
+        let mut app = App::default();
+        let peer_code_id = app.store_code(peer());
+        let manager_code_id = app.store_code(manager());
+
+        let manager_address = app
+            .instantiate_contract(
+                manager_code_id,
+                Addr::unchecked("manager_creator_address"),
+                &InstantiateMsg{
+                    peer_code_id,
+                    incremental_donation: coin(100, "utdg"),
+                    collective_ratio: Decimal::percent(60),
+                    other_denoms: DenomPolicy::PassThrough,
+                    zero_weight: ZeroWeightPolicy::Hold,
+                    predictable_addresses: false,
+                    weight_strategy: WeightStrategy::Linear,
+                    floor_share: Decimal::zero(),
+                    epoch_length: 0,
+                    decay: Decay::None,
+                    donor_share: Decimal::zero(),
+                },
+                &[],
+                "manager",
+                None)
+            .unwrap();
+
+        // Manager - ExecuteMsg::Join {} from "creator_address"
+        app.execute_contract(Addr::unchecked("creator_address"), manager_address.clone(), &ExecuteMsg::Join {}, &[]).unwrap();
+
+        // Manager - QueryMsg::MemberPeerAddr { addr: "creator_address".to_string() }
+        let peer : MemberPeerAddrResp = app
+            .wrap()
+            .query_wasm_smart(manager_address.clone(), &QueryMsg::MemberPeerAddr { addr: "creator_address".to_string() })
+            .unwrap();
+
+        // Peer - QueryMsg::Owner . The peer address is obtained in the previous step - MemberPeerAddrResp.addr
+        let owner_resp : OwnerResp = app
+            .wrap()
+            .query_wasm_smart(peer.addr.clone(), &donation_peer::msg::QueryMsg::Owner {})
+            .unwrap();
+        assert_eq!(Addr::unchecked("creator_address"),owner_resp.owner);
+
+        //Peer - QueryMsg::Manager . It should be the contract that created the peer i.e. the manager
+        let manager_resp : ManagerResp = app
+            .wrap()
+            .query_wasm_smart(peer.addr.clone(),&donation_peer::msg::QueryMsg::Manager {})
+            .unwrap();
+        assert_eq!(manager_address, manager_resp.manager);
+        assert!(!manager_resp.detached);
+
+        //Peer - QueryMsg::Donators. It should be zero
+        let donators_resp : DonatorsResponse = app
+            .wrap()
+            .query_wasm_smart(peer.addr,&donation_peer::msg::QueryMsg::Donators {})
+            .unwrap();
+        assert_eq!(donators_resp.donators,0);
+    }
+
+
+    #[test]
+    fn single_peer_single_donate() {
+        // After a single donation, the only peer should get the whole amount back from the manager
+        let mut app = App::new(|router, _api, storage| {
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked("donator"), coins(100, "utgd"))
+                .unwrap();
+        });
+        let manager = setup_manager(&mut app);
+
+        app.execute_contract(
+            Addr::unchecked("member"),
+            manager.clone(),
+            &ExecuteMsg::Join {},
+            &[],
+        )
+        .unwrap();
+
+        let peer: MemberPeerAddrResp = app
+            .wrap()
+            .query_wasm_smart(
+                manager.clone(),
+                &QueryMsg::MemberPeerAddr {
+                    addr: "member".to_owned(),
+                },
+            )
+            .unwrap();
+
+        app.execute_contract(
+            Addr::unchecked("donator"),
+            peer.addr.clone(),
+            &PeerExecMsg::Donate { memo: None, anonymous: None },
+            &coins(100, "utgd"),
+        )
+        .unwrap();
+
+        let donators_resp: DonatorsResponse = app
+            .wrap()
+            .query_wasm_smart(peer.addr.clone(), &donation_peer::msg::QueryMsg::Donators {})
+            .unwrap();
+        assert_eq!(donators_resp.donators, 1);
+
+        // The collective share waits on the manager until the peer claims it
+        let pending: PendingRewardsResp = app
+            .wrap()
+            .query_wasm_smart(manager.clone(), &QueryMsg::PendingRewards { peer: peer.addr.to_string() })
+            .unwrap();
+        assert_eq!(pending.rewards, coins(60, "utgd"));
+
+        app.execute_contract(Addr::unchecked("member"), manager.clone(), &ExecuteMsg::Claim {}, &[])
+            .unwrap();
+
+        app.execute_contract(
+            Addr::unchecked("member"),
+            peer.addr.clone(),
+            &PeerExecMsg::Withdraw { amount: None, receiver: None },
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(
+            coin(0, "utgd"),
+            app.wrap().query_balance("donator", "utgd").unwrap()
+        );
+        assert_eq!(
+            coin(0, "utgd"),
+            app.wrap().query_balance(manager.as_str(), "utgd").unwrap()
+        );
+        assert_eq!(
+            coin(0, "utgd"),
+            app.wrap()
+                .query_balance(peer.addr.as_str(), "utgd")
+                .unwrap()
+        );
+        assert_eq!(
+            coin(100, "utgd"),
+            app.wrap().query_balance("member", "utgd").unwrap()
+        );
+    }
+
+    #[test]
+    fn donate_without_funds_fails() {
+        let mut app = App::default();
+        let manager = setup_manager(&mut app);
+
+        app.execute_contract(Addr::unchecked("member"), manager.clone(), &ExecuteMsg::Join {}, &[])
+            .unwrap();
+        let peer: MemberPeerAddrResp = app
+            .wrap()
+            .query_wasm_smart(manager, &QueryMsg::MemberPeerAddr { addr: "member".to_owned() })
+            .unwrap();
+
+        let err = app
+            .execute_contract(Addr::unchecked("donator"), peer.addr, &PeerExecMsg::Donate { memo: None, anonymous: None }, &[])
+            .unwrap_err();
+        assert_eq!(
+            donation_peer::ContractError::NoFunds {},
+            err.downcast().unwrap()
+        );
+    }
+
+    #[test]
+    fn dust_donation_is_held_by_manager() {
+        // A donation below incremental_donation adds no weight, so the manager has nobody to pay
+        let mut app = App::new(|router, _api, storage| {
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked("donator"), coins(150, "utgd"))
+                .unwrap();
+        });
+        let manager = setup_manager(&mut app);
+        let peer = join_as(&mut app, &manager, "member");
+
+        app.execute_contract(Addr::unchecked("donator"), peer.clone(), &PeerExecMsg::Donate { memo: None, anonymous: None }, &coins(50, "utgd"))
+            .unwrap();
+
+        assert_eq!(
+            coin(20, "utgd"),
+            app.wrap().query_balance(peer.as_str(), "utgd").unwrap()
+        );
+        assert_eq!(
+            coin(30, "utgd"),
+            app.wrap().query_balance(manager.as_str(), "utgd").unwrap()
+        );
+        let pending: PendingRewardsResp = app
+            .wrap()
+            .query_wasm_smart(manager.clone(), &QueryMsg::PendingRewards { peer: peer.to_string() })
+            .unwrap();
+        assert_eq!(pending.rewards, vec![]);
+
+        // Once the peer has weight, held funds are distributed with the next donation
+        app.execute_contract(Addr::unchecked("donator"), peer.clone(), &PeerExecMsg::Donate { memo: None, anonymous: None }, &coins(100, "utgd"))
+            .unwrap();
+        let pending: PendingRewardsResp = app
+            .wrap()
+            .query_wasm_smart(manager, &QueryMsg::PendingRewards { peer: peer.to_string() })
+            .unwrap();
+        assert_eq!(pending.rewards, coins(90, "utgd"));
+    }
+
+    #[test]
+    fn leave_settles_and_detaches() {
+        let mut app = App::new(|router, _api, storage| {
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked("donator"), coins(1000, "utgd"))
+                .unwrap();
+        });
+        let manager = setup_manager(&mut app);
+        let peer1 = join_as(&mut app, &manager, "member1");
+        let peer2 = join_as(&mut app, &manager, "member2");
+
+        // Both peers get one donator: peer1 is owed 60 + 30, peer2 is owed 30
+        app.execute_contract(Addr::unchecked("donator"), peer1.clone(), &PeerExecMsg::Donate { memo: None, anonymous: None }, &coins(100, "utgd"))
+            .unwrap();
+        app.execute_contract(Addr::unchecked("donator"), peer2.clone(), &PeerExecMsg::Donate { memo: None, anonymous: None }, &coins(100, "utgd"))
+            .unwrap();
+        assert_eq!(coin(40, "utgd"), app.wrap().query_balance(peer1.as_str(), "utgd").unwrap());
+        assert_eq!(coin(40, "utgd"), app.wrap().query_balance(peer2.as_str(), "utgd").unwrap());
+        assert_eq!(coin(120, "utgd"), app.wrap().query_balance(manager.as_str(), "utgd").unwrap());
+
+        let err = app
+            .execute_contract(Addr::unchecked("stranger"), manager.clone(), &ExecuteMsg::Leave {}, &[])
+            .unwrap_err();
+        assert_eq!(ContractError::NotMember {}, err.downcast().unwrap());
+
+        // Leaving pays out everything the peer is owed
+        app.execute_contract(Addr::unchecked("member1"), manager.clone(), &ExecuteMsg::Leave {}, &[])
+            .unwrap();
+        assert_eq!(coin(130, "utgd"), app.wrap().query_balance(peer1.as_str(), "utgd").unwrap());
+        assert_eq!(coin(30, "utgd"), app.wrap().query_balance(manager.as_str(), "utgd").unwrap());
+
+        let manager_resp: ManagerResp = app
+            .wrap()
+            .query_wasm_smart(peer1.clone(), &donation_peer::msg::QueryMsg::Manager {})
+            .unwrap();
+        assert!(manager_resp.detached);
+
+        // The detached peer keeps whole donations, and the manager only pays the remaining member
+        app.execute_contract(Addr::unchecked("donator"), peer1.clone(), &PeerExecMsg::Donate { memo: None, anonymous: None }, &coins(100, "utgd"))
+            .unwrap();
+        app.execute_contract(Addr::unchecked("donator"), peer2.clone(), &PeerExecMsg::Donate { memo: None, anonymous: None }, &coins(100, "utgd"))
+            .unwrap();
+        app.execute_contract(Addr::unchecked("member2"), manager.clone(), &ExecuteMsg::Claim {}, &[])
+            .unwrap();
+        assert_eq!(coin(230, "utgd"), app.wrap().query_balance(peer1.as_str(), "utgd").unwrap());
+        assert_eq!(coin(170, "utgd"), app.wrap().query_balance(peer2.as_str(), "utgd").unwrap());
+        assert_eq!(coin(0, "utgd"), app.wrap().query_balance(manager.as_str(), "utgd").unwrap());
+    }
+
+    // Read straight from the storage of the manager, there is no query for it
+    fn held_of(app: &App, manager: &Addr, denom: &str) -> Uint128 {
+        app.wrap()
+            .query_wasm_raw(manager, HELD.key(denom).to_vec())
+            .unwrap()
+            .map(|held| from_json(held).unwrap())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn fractions_of_leaving_members_are_held() {
+        let mut app = App::new(|router, _api, storage| {
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked("donator"), coins(305, "utgd"))
+                .unwrap();
+        });
+        let manager = setup_manager(&mut app);
+        let peer1 = join_as(&mut app, &manager, "member1");
+        let peer2 = join_as(&mut app, &manager, "member2");
+        let donate = PeerExecMsg::Donate { memo: None, anonymous: None };
+
+        // Peer1 is owed 60 + 30 + 2.5, peer2 30 + 2.5
+        app.execute_contract(Addr::unchecked("donator"), peer1.clone(), &donate, &coins(100, "utgd"))
+            .unwrap();
+        app.execute_contract(Addr::unchecked("donator"), peer2.clone(), &donate, &coins(100, "utgd"))
+            .unwrap();
+        app.execute_contract(Addr::unchecked("donator"), manager.clone(), &ExecuteMsg::Donate {}, &coins(5, "utgd"))
+            .unwrap();
+
+        // The half units left behind add up to one, which waits for the next distribution
+        app.execute_contract(Addr::unchecked("member1"), manager.clone(), &ExecuteMsg::Leave {}, &[])
+            .unwrap();
+        assert_eq!(coin(132, "utgd"), app.wrap().query_balance(peer1.as_str(), "utgd").unwrap());
+        assert_eq!(held_of(&app, &manager, "utgd"), Uint128::zero());
+        app.execute_contract(Addr::unchecked("member2"), manager.clone(), &ExecuteMsg::Leave {}, &[])
+            .unwrap();
+        assert_eq!(coin(72, "utgd"), app.wrap().query_balance(peer2.as_str(), "utgd").unwrap());
+        assert_eq!(held_of(&app, &manager, "utgd"), Uint128::new(1));
+        assert_eq!(coin(1, "utgd"), app.wrap().query_balance(manager.as_str(), "utgd").unwrap());
+
+        let peer3 = join_as(&mut app, &manager, "member3");
+        app.execute_contract(Addr::unchecked("donator"), peer3.clone(), &donate, &coins(100, "utgd"))
+            .unwrap();
+        assert_eq!(pending_of(&app, &manager, &peer3), coins(61, "utgd"));
+        assert_eq!(held_of(&app, &manager, "utgd"), Uint128::zero());
+        assert_eq!(coin(61, "utgd"), app.wrap().query_balance(manager.as_str(), "utgd").unwrap());
+    }
+
+    #[test]
+    fn duplicate_join_fails() {
+        let mut app = App::default();
+        let manager = setup_manager(&mut app);
+        let peer1 = join_as(&mut app, &manager, "member1");
+
+        let err = app
+            .execute_contract(Addr::unchecked("member1"), manager.clone(), &ExecuteMsg::Join {}, &[])
+            .unwrap_err();
+        assert_eq!(ContractError::AlreadyMember {}, err.downcast().unwrap());
+
+        // Next creator gets its own peer, and the first one is still wired to its owner
+        let peer2 = join_as(&mut app, &manager, "member2");
+        assert_ne!(peer1, peer2);
+
+        for (peer, owner) in [(peer1, "member1"), (peer2, "member2")] {
+            let owner_resp: OwnerResp = app
+                .wrap()
+                .query_wasm_smart(peer, &donation_peer::msg::QueryMsg::Owner {})
+                .unwrap();
+            assert_eq!(owner_resp.owner, owner);
+        }
+    }
+
+    #[test]
+    fn unexpected_calls_are_typed_errors() {
+        let mut deps = mock_dependencies();
+
+        let err = reply(deps.as_mut(), mock_env(), Reply {
+            id: 7,
+            result: SubMsgResult::Err("failed".to_string()),
+        }).unwrap_err();
+        assert_eq!(ContractError::UnknownReplyId(7), err);
+
+        // Only donations carry funds
+        let err = execute(deps.as_mut(), mock_env(), mock_info("member", &coins(10, "utgd")), ExecuteMsg::Join {})
+            .unwrap_err();
+        assert_eq!(ContractError::Payment(PaymentError::NonPayable {}), err);
+    }
+
+    #[test]
+    fn member_peer_addr_of_non_member() {
+        let mut app = App::default();
+        let manager = setup_manager(&mut app);
+        join_as(&mut app, &manager, "member");
+
+        let err = app
+            .wrap()
+            .query_wasm_smart::<MemberPeerAddrResp>(manager, &QueryMsg::MemberPeerAddr { addr: "stranger".to_owned() })
+            .unwrap_err();
+        assert!(err.to_string().contains(&ContractError::NotMember {}.to_string()));
+    }
+
+    #[test]
+    fn members_listing() {
+        let mut app = App::new(|router, _api, storage| {
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked("donator"), coins(200, "utgd"))
+                .unwrap();
+        });
+        let manager = setup_manager(&mut app);
+        let mut peers = vec![];
+        for owner in ["member1", "member2", "member3"] {
+            peers.push((join_as(&mut app, &manager, owner), owner));
+            app.update_block(|block| block.height += 1);
+        }
+        let joined_at = app.block_info().height - 3;
+
+        app.execute_contract(Addr::unchecked("donator"), peers[1].0.clone(), &PeerExecMsg::Donate { memo: None, anonymous: None }, &coins(200, "utgd"))
+            .unwrap();
+
+        let count: MemberCountResp = app
+            .wrap()
+            .query_wasm_smart(manager.clone(), &QueryMsg::MemberCount {})
+            .unwrap();
+        assert_eq!(count.count, 3);
+
+        // Members are listed by peer address
+        let mut expected: Vec<_> = peers
+            .iter()
+            .enumerate()
+            .map(|(idx, (peer, owner))| (peer.clone(), Addr::unchecked(*owner), if idx == 1 { 2 } else { 0 }, joined_at + idx as u64))
+            .collect();
+        expected.sort();
+
+        let list = |start_after: Option<String>, limit| -> Vec<_> {
+            let resp: MembersResp = app
+                .wrap()
+                .query_wasm_smart(manager.clone(), &QueryMsg::Members { start_after, limit })
+                .unwrap();
+            resp.members
+                .into_iter()
+                .map(|member| (member.peer, member.owner, member.donators, member.joined_height))
+                .collect()
+        };
+
+        assert_eq!(list(None, None), expected);
+        assert_eq!(list(None, Some(2)), expected[..2]);
+        assert_eq!(list(Some(expected[1].0.to_string()), Some(2)), expected[2..]);
+
+        app.execute_contract(Addr::unchecked("member2"), manager.clone(), &ExecuteMsg::Leave {}, &[])
+            .unwrap();
+        let count: MemberCountResp = app
+            .wrap()
+            .query_wasm_smart(manager, &QueryMsg::MemberCount {})
+            .unwrap();
+        assert_eq!(count.count, 2);
+    }
+
+    #[test]
+    fn rewards_are_claimed_by_weight() {
+        let mut app = App::new(|router, _api, storage| {
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked("donator"), coins(1000, "utgd"))
+                .unwrap();
+        });
+        let manager = setup_manager(&mut app);
+        let owners = ["member1", "member2", "member3"];
+        let peers: Vec<_> = owners
+            .into_iter()
+            .map(|owner| join_as(&mut app, &manager, owner))
+            .collect();
+
+        // Weights end up 1:2:1. Forwarded 60 goes to peer1 alone, then 120 is split 1:2 and 60 is split 1:2:1
+        for (peer, amount) in peers.iter().zip([100, 200, 100]) {
+            app.execute_contract(Addr::unchecked("donator"), peer.clone(), &PeerExecMsg::Donate { memo: None, anonymous: None }, &coins(amount, "utgd"))
+                .unwrap();
+        }
+        // 7 donated straight to the manager is 1.75 per weight, fractions are kept for later
+        app.execute_contract(Addr::unchecked("donator"), manager.clone(), &ExecuteMsg::Donate {}, &coins(7, "utgd"))
+            .unwrap();
+
+        let pending = |app: &App, peer: &Addr| -> Vec<Coin> {
+            let resp: PendingRewardsResp = app
+                .wrap()
+                .query_wasm_smart(manager.clone(), &QueryMsg::PendingRewards { peer: peer.to_string() })
+                .unwrap();
+            resp.rewards
+        };
+        assert_eq!(pending(&app, &peers[0]), coins(116, "utgd"));
+        assert_eq!(pending(&app, &peers[1]), coins(113, "utgd"));
+        assert_eq!(pending(&app, &peers[2]), coins(16, "utgd"));
+
+        // Another unit completes the fractions
+        app.execute_contract(Addr::unchecked("donator"), manager.clone(), &ExecuteMsg::Donate {}, &coins(1, "utgd"))
+            .unwrap();
+        for owner in owners {
+            app.execute_contract(Addr::unchecked(owner), manager.clone(), &ExecuteMsg::Claim {}, &[])
+                .unwrap();
+        }
+
+        assert_eq!(coin(0, "utgd"), app.wrap().query_balance(manager.as_str(), "utgd").unwrap());
+        assert_eq!(coin(157, "utgd"), app.wrap().query_balance(peers[0].as_str(), "utgd").unwrap());
+        assert_eq!(coin(194, "utgd"), app.wrap().query_balance(peers[1].as_str(), "utgd").unwrap());
+        assert_eq!(coin(57, "utgd"), app.wrap().query_balance(peers[2].as_str(), "utgd").unwrap());
+
+        let err = app
+            .execute_contract(Addr::unchecked("member1"), manager.clone(), &ExecuteMsg::Claim {}, &[])
+            .unwrap_err();
+        assert_eq!(ContractError::NothingToClaim {}, err.downcast().unwrap());
+
+        let err = app
+            .execute_contract(Addr::unchecked("stranger"), manager, &ExecuteMsg::Claim {}, &[])
+            .unwrap_err();
+        assert_eq!(ContractError::NotMember {}, err.downcast().unwrap());
+    }
+
+    #[test]
+    fn zero_weight_split_equally() {
+        let mut app = App::new(|router, _api, storage| {
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked("donator"), coins(50, "utgd"))
+                .unwrap();
+        });
+        let manager = setup_manager_with(&mut app, |msg| InstantiateMsg {
+            zero_weight: ZeroWeightPolicy::SplitEqually,
+            ..msg
+        });
+        let peer1 = join_as(&mut app, &manager, "member1");
+        let peer2 = join_as(&mut app, &manager, "member2");
+
+        // Dust donation: peer1 keeps 20, the forwarded 30 is split 15/15 although nobody has weight
+        app.execute_contract(Addr::unchecked("donator"), peer1.clone(), &PeerExecMsg::Donate { memo: None, anonymous: None }, &coins(50, "utgd"))
+            .unwrap();
+        for owner in ["member1", "member2"] {
+            app.execute_contract(Addr::unchecked(owner), manager.clone(), &ExecuteMsg::Claim {}, &[])
+                .unwrap();
+        }
+        assert_eq!(coin(35, "utgd"), app.wrap().query_balance(peer1.as_str(), "utgd").unwrap());
+        assert_eq!(coin(15, "utgd"), app.wrap().query_balance(peer2.as_str(), "utgd").unwrap());
+        assert_eq!(coin(0, "utgd"), app.wrap().query_balance(manager.as_str(), "utgd").unwrap());
+    }
+
+    #[test]
+    fn update_weight_only_from_peers() {
+        let mut app = App::default();
+        let manager = setup_manager(&mut app);
+        join_as(&mut app, &manager, "member");
+
+        // Neither the owner nor anyone else can fake the weight of a peer
+        for sender in ["member", "stranger"] {
+            let err = app
+                .execute_contract(Addr::unchecked(sender), manager.clone(), &ExecuteMsg::UpdateWeight { weight: 100, unique_donors: 1 }, &[])
+                .unwrap_err();
+            assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+        }
+    }
+
+    #[test]
+    fn admin_transfer() {
+        let mut app = App::default();
+        let manager = setup_manager(&mut app);
+
+        let admin = |app: &App| -> AdminResp {
+            app.wrap().query_wasm_smart(manager.clone(), &QueryMsg::Admin {}).unwrap()
+        };
+        assert_eq!(admin(&app), AdminResp { admin: Some(Addr::unchecked("admin")), pending: None });
+
+        let err = app
+            .execute_contract(Addr::unchecked("stranger"), manager.clone(), &ExecuteMsg::ProposeAdmin { admin: "stranger".to_owned() }, &[])
+            .unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+
+        app.execute_contract(Addr::unchecked("admin"), manager.clone(), &ExecuteMsg::ProposeAdmin { admin: "new_admin".to_owned() }, &[])
+            .unwrap();
+        assert_eq!(
+            admin(&app),
+            AdminResp { admin: Some(Addr::unchecked("admin")), pending: Some(Addr::unchecked("new_admin")) }
+        );
+
+        // Only the proposed admin can accept
+        let err = app
+            .execute_contract(Addr::unchecked("stranger"), manager.clone(), &ExecuteMsg::AcceptAdmin {}, &[])
+            .unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+
+        app.execute_contract(Addr::unchecked("new_admin"), manager.clone(), &ExecuteMsg::AcceptAdmin {}, &[])
+            .unwrap();
+        assert_eq!(admin(&app), AdminResp { admin: Some(Addr::unchecked("new_admin")), pending: None });
+
+        app.execute_contract(Addr::unchecked("new_admin"), manager.clone(), &ExecuteMsg::RenounceAdmin {}, &[])
+            .unwrap();
+        assert_eq!(admin(&app), AdminResp { admin: None, pending: None });
+
+        let err = app
+            .execute_contract(Addr::unchecked("new_admin"), manager.clone(), &ExecuteMsg::RenounceAdmin {}, &[])
+            .unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+    }
+
+    #[test]
+    fn update_and_propagate_config() {
+        let mut app = App::new(|router, _api, storage| {
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked("donator"), coins(1000, "utgd"))
+                .unwrap();
+        });
+        let manager = setup_manager(&mut app);
+        let old_peer = join_as(&mut app, &manager, "member1");
+
+        let update = |ratio| ExecuteMsg::UpdateConfig(Box::new(ConfigUpdate {
+            collective_ratio: Some(ratio),
+            ..Default::default()
+        }));
+
+        let err = app
+            .execute_contract(Addr::unchecked("member1"), manager.clone(), &update(Decimal::percent(50)), &[])
+            .unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+
+        let err = app
+            .execute_contract(Addr::unchecked("admin"), manager.clone(), &update(Decimal::percent(101)), &[])
+            .unwrap_err();
+        assert_eq!(ContractError::InvalidRatio {}, err.downcast().unwrap());
+
+        app.execute_contract(Addr::unchecked("admin"), manager.clone(), &update(Decimal::percent(50)), &[])
+            .unwrap();
+        let config: GetConfigResponse = app
+            .wrap()
+            .query_wasm_smart(manager.clone(), &QueryMsg::Config {})
+            .unwrap();
+        assert_eq!(config.config.unwrap().collective_ratio, Decimal::percent(50));
+
+        // New peers use the new ratio right away, existing ones after the propagation
+        let new_peer = join_as(&mut app, &manager, "member2");
+        app.execute_contract(Addr::unchecked("donator"), new_peer.clone(), &PeerExecMsg::Donate { memo: None, anonymous: None }, &coins(100, "utgd"))
+            .unwrap();
+        assert_eq!(coin(50, "utgd"), app.wrap().query_balance(new_peer.as_str(), "utgd").unwrap());
+
+        app.execute_contract(Addr::unchecked("donator"), old_peer.clone(), &PeerExecMsg::Donate { memo: None, anonymous: None }, &coins(100, "utgd"))
+            .unwrap();
+        assert_eq!(coin(40, "utgd"), app.wrap().query_balance(old_peer.as_str(), "utgd").unwrap());
+
+        let err = app
+            .execute_contract(Addr::unchecked("member1"), manager.clone(), &ExecuteMsg::PropagateConfig { start_after: None, limit: None }, &[])
+            .unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+
+        app.execute_contract(Addr::unchecked("admin"), manager.clone(), &ExecuteMsg::PropagateConfig { start_after: None, limit: None }, &[])
+            .unwrap();
+        app.execute_contract(Addr::unchecked("donator"), old_peer.clone(), &PeerExecMsg::Donate { memo: None, anonymous: None }, &coins(100, "utgd"))
+            .unwrap();
+        assert_eq!(coin(90, "utgd"), app.wrap().query_balance(old_peer.as_str(), "utgd").unwrap());
+    }
+
+    #[test]
+    fn migrate_peers_in_pages() {
+        let mut app = App::default();
+        let manager = setup_manager(&mut app);
+        let peers = [
+            join_as(&mut app, &manager, "member1"),
+            join_as(&mut app, &manager, "member2"),
+            join_as(&mut app, &manager, "member3"),
+        ];
+        let new_code_id = app.store_code(peer());
+
+        let migrate = |start_after: Option<String>| ExecuteMsg::MigratePeers {
+            new_code_id,
+            msg: to_json_binary(&donation_peer::msg::MigrateMsg {}).unwrap(),
+            start_after,
+            limit: Some(2),
+        };
+
+        let err = app
+            .execute_contract(Addr::unchecked("member1"), manager.clone(), &migrate(None), &[])
+            .unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+
+        // Go through the pages until one comes back empty
+        let mut start_after = None;
+        loop {
+            let resp = app
+                .execute_contract(Addr::unchecked("admin"), manager.clone(), &migrate(start_after), &[])
+                .unwrap();
+            start_after = resp
+                .events
+                .iter()
+                .flat_map(|event| &event.attributes)
+                .find(|attr| attr.key == "last")
+                .map(|attr| attr.value.clone());
+            if start_after.is_none() {
+                break;
+            }
+        }
+
+        for peer in &peers {
+            let info = app.wrap().query_wasm_contract_info(peer).unwrap();
+            assert_eq!(info.code_id, new_code_id);
+            assert_eq!(info.admin, Some(manager.to_string()));
+        }
+
+        let config: GetConfigResponse = app
+            .wrap()
+            .query_wasm_smart(manager, &QueryMsg::Config {})
+            .unwrap();
+        assert_eq!(config.config.unwrap().peer_code_id, new_code_id);
+    }
+
+    #[test]
+    fn migrate_peers_skips_foreign_admins() {
+        let mut app = App::default();
+        let manager = setup_manager(&mut app);
+        let peers = [
+            join_as(&mut app, &manager, "member1"),
+            join_as(&mut app, &manager, "member2"),
+            join_as(&mut app, &manager, "member3"),
+        ];
+        let old_code_id = app.wrap().query_wasm_contract_info(&peers[1]).unwrap().code_id;
+        let new_code_id = app.store_code(peer());
+
+        // Stands for a peer which joined while peers were instantiated with another admin
+        let handover = WasmMsg::UpdateAdmin { contract_addr: peers[1].to_string(), admin: "legacy".to_string() };
+        app.execute(manager.clone(), handover.into()).unwrap();
+
+        let migrate = ExecuteMsg::MigratePeers {
+            new_code_id,
+            msg: to_json_binary(&donation_peer::msg::MigrateMsg {}).unwrap(),
+            start_after: None,
+            limit: None,
+        };
+        let resp = app
+            .execute_contract(Addr::unchecked("admin"), manager.clone(), &migrate, &[])
+            .unwrap();
+        let attr = |key: &str| {
+            resp.events
+                .iter()
+                .flat_map(|event| &event.attributes)
+                .find(|attr| attr.key == key)
+                .map(|attr| attr.value.clone())
+        };
+        assert_eq!(attr("count"), Some("2".to_string()));
+        assert_eq!(attr("skipped"), Some(peers[1].to_string()));
+
+        let code_ids: Vec<_> = peers
+            .iter()
+            .map(|peer| app.wrap().query_wasm_contract_info(peer).unwrap().code_id)
+            .collect();
+        assert_eq!(code_ids, vec![new_code_id, old_code_id, new_code_id]);
+    }
+
+    #[test]
+    fn predictable_peer_addresses() {
+        // Instantiate2 addresses need a real address format
+        let mut app = AppBuilder::default()
+            .with_api(MockApiBech32::new("juno"))
+            .with_wasm(WasmKeeper::default().with_address_generator(MockAddressGenerator))
+            .build(|_, _, _| {});
+        let admin = app.api().addr_make("admin");
+        let owner = app.api().addr_make("member");
+        let peer_code_id = app.store_code(peer());
+        let manager_code_id = app.store_code(manager());
+
+        let manager = app
+            .instantiate_contract(
+                manager_code_id,
+                admin.clone(),
+                &InstantiateMsg {
+                    predictable_addresses: true,
+                    ..instantiate_msg(peer_code_id)
+                },
+                &[],
+                "manager",
+                None,
+            )
+            .unwrap();
+
+        let predict = |app: &App<_, MockApiBech32>| -> Addr {
+            let resp: PredictPeerAddrResp = app
+                .wrap()
+                .query_wasm_smart(manager.clone(), &QueryMsg::PredictPeerAddr { owner: owner.to_string() })
+                .unwrap();
+            resp.addr
+        };
+
+        let predicted = predict(&app);
+        app.execute_contract(owner.clone(), manager.clone(), &ExecuteMsg::Join {}, &[])
+            .unwrap();
+        let peer: MemberPeerAddrResp = app
+            .wrap()
+            .query_wasm_smart(manager.clone(), &QueryMsg::MemberPeerAddr { addr: owner.to_string() })
+            .unwrap();
+        assert_eq!(peer.addr, predicted);
+        let owner_resp: OwnerResp = app
+            .wrap()
+            .query_wasm_smart(predicted.clone(), &donation_peer::msg::QueryMsg::Owner {})
+            .unwrap();
+        assert_eq!(owner_resp.owner, owner);
+
+        // After leaving, the owner joins again with a fresh address
+        app.execute_contract(owner.clone(), manager.clone(), &ExecuteMsg::Leave {}, &[])
+            .unwrap();
+        let repredicted = predict(&app);
+        assert_ne!(repredicted, predicted);
+        app.execute_contract(owner.clone(), manager.clone(), &ExecuteMsg::Join {}, &[])
+            .unwrap();
+        let members: MemberCountResp = app
+            .wrap()
+            .query_wasm_smart(manager.clone(), &QueryMsg::MemberCount {})
+            .unwrap();
+        assert_eq!(members.count, 1);
+        app.wrap().query_wasm_contract_info(repredicted).unwrap();
+
+        // Without Instantiate2 there is nothing to predict
+        app.execute_contract(admin, manager.clone(), &ExecuteMsg::UpdateConfig(Box::new(ConfigUpdate {
+            predictable_addresses: Some(false),
+            ..Default::default()
+        })), &[]).unwrap();
+        let err = app
+            .wrap()
+            .query_wasm_smart::<PredictPeerAddrResp>(manager, &QueryMsg::PredictPeerAddr { owner: owner.to_string() })
+            .unwrap_err();
+        assert!(err.to_string().contains(&ContractError::NotPredictable {}.to_string()));
+    }
+
+    #[test]
+    fn only_allowed_denoms_are_donated() {
+        let mut app = App::new(|router, _api, storage| {
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked("donator"), vec![coin(1000, "utgd"), coin(1000, "uatom"), coin(1000, "ujuno")])
+                .unwrap();
+        });
+        let manager = setup_manager(&mut app);
+        app.execute_contract(Addr::unchecked("admin"), manager.clone(), &ExecuteMsg::UpdateConfig(Box::new(ConfigUpdate {
+            other_denoms: Some(DenomPolicy::Allowlist(vec![coin(10, "uatom")])),
+            ..Default::default()
+        })), &[]).unwrap();
+        let peer = join_as(&mut app, &manager, "member");
+
+        let err = app
+            .execute_contract(Addr::unchecked("donator"), manager.clone(), &ExecuteMsg::Donate {}, &[coin(10, "uatom"), coin(10, "ujuno")])
+            .unwrap_err();
+        assert_eq!(ContractError::InvalidDenom { denom: "ujuno".to_string() }, err.downcast().unwrap());
+
+        // Peers get the same allowlist
+        let err = app
+            .execute_contract(Addr::unchecked("donator"), peer.clone(), &PeerExecMsg::Donate { memo: None, anonymous: None }, &coins(10, "ujuno"))
+            .unwrap_err();
+        assert_eq!(
+            donation_peer::ContractError::InvalidDenom { denom: "ujuno".to_string() },
+            err.downcast().unwrap()
+        );
+
+        // 20uatom is two increments, the collective share of it is owed to the only member
+        app.execute_contract(Addr::unchecked("donator"), peer.clone(), &PeerExecMsg::Donate { memo: None, anonymous: None }, &coins(20, "uatom"))
+            .unwrap();
+        let resp: DonatorsResponse = app
+            .wrap()
+            .query_wasm_smart(peer.clone(), &donation_peer::msg::QueryMsg::Donators {})
+            .unwrap();
+        assert_eq!(resp.donators, 2);
+        let rewards: PendingRewardsResp = app
+            .wrap()
+            .query_wasm_smart(manager.clone(), &QueryMsg::PendingRewards { peer: peer.to_string() })
+            .unwrap();
+        assert_eq!(rewards.rewards, coins(12, "uatom"));
+
+        // Until the new policy is propagated the peer still forwards uatom, which the manager takes from it
+        app.execute_contract(Addr::unchecked("admin"), manager.clone(), &ExecuteMsg::UpdateConfig(Box::new(ConfigUpdate {
+            other_denoms: Some(DenomPolicy::Allowlist(vec![])),
+            ..Default::default()
+        })), &[]).unwrap();
+        let err = app
+            .execute_contract(Addr::unchecked("donator"), manager.clone(), &ExecuteMsg::Donate {}, &coins(10, "uatom"))
+            .unwrap_err();
+        assert_eq!(ContractError::InvalidDenom { denom: "uatom".to_string() }, err.downcast().unwrap());
+        app.execute_contract(Addr::unchecked("donator"), peer.clone(), &PeerExecMsg::Donate { memo: None, anonymous: None }, &coins(10, "uatom"))
+            .unwrap();
+        let rewards: PendingRewardsResp = app
+            .wrap()
+            .query_wasm_smart(manager, &QueryMsg::PendingRewards { peer: peer.to_string() })
+            .unwrap();
+        assert_eq!(rewards.rewards, coins(18, "uatom"));
+    }
+
+    #[test]
+    fn cw20_donations() {
+        let mut app = App::default();
+        let token_code_id = app.store_code(Box::new(ContractWrapper::new(
+            cw20_base::contract::execute,
+            cw20_base::contract::instantiate,
+            cw20_base::contract::query,
+        )));
+        let mut token = |symbol: &str| {
+            let msg = cw20_base::msg::InstantiateMsg {
+                name: "Community token".to_string(),
+                symbol: symbol.to_string(),
+                decimals: 6,
+                initial_balances: vec![Cw20Coin { address: "donator".to_string(), amount: Uint128::new(1000) }],
+                mint: None,
+                marketing: None,
+            };
+            app.instantiate_contract(token_code_id, Addr::unchecked("admin"), &msg, &[], symbol, None)
+                .unwrap()
+        };
+        let listed = token("LISTED");
+        let unlisted = token("UNLISTED");
+        let balance = |app: &App, token: &Addr, addr: &Addr| -> u128 {
+            let resp: BalanceResponse = app
+                .wrap()
+                .query_wasm_smart(token, &Cw20QueryMsg::Balance { address: addr.to_string() })
+                .unwrap();
+            resp.balance.u128()
+        };
+
+        let manager = setup_manager(&mut app);
+        app.execute_contract(Addr::unchecked("admin"), manager.clone(), &ExecuteMsg::UpdateConfig(Box::new(ConfigUpdate {
+            other_denoms: Some(DenomPolicy::Allowlist(vec![coin(10, cw20_denom(listed.as_str()))])),
+            ..Default::default()
+        })), &[]).unwrap();
+        let peer = join_as(&mut app, &manager, "member");
+
+        let send = |contract: &Addr, amount| Cw20ExecuteMsg::Send {
+            contract: contract.to_string(),
+            amount: Uint128::new(amount),
+            msg: to_json_binary(&ReceiveMsg::Donate {}).unwrap(),
+        };
+
+        let err = app
+            .execute_contract(Addr::unchecked("donator"), unlisted.clone(), &send(&manager, 10), &[])
+            .unwrap_err();
+        assert_eq!(
+            ContractError::InvalidDenom { denom: cw20_denom(unlisted.as_str()) },
+            err.downcast().unwrap()
+        );
+
+        // 25 tokens are two increments, 15 of them go to the manager and are owed to the only member
+        app.execute_contract(Addr::unchecked("donator"), listed.clone(), &send(&peer, 25), &[])
+            .unwrap();
+        let resp: DonatorsResponse = app
+            .wrap()
+            .query_wasm_smart(peer.clone(), &donation_peer::msg::QueryMsg::Donators {})
+            .unwrap();
+        assert_eq!(resp.donators, 2);
+        assert_eq!(balance(&app, &listed, &peer), 10);
+        assert_eq!(balance(&app, &listed, &manager), 15);
+
+        app.execute_contract(Addr::unchecked("member"), manager.clone(), &ExecuteMsg::Claim {}, &[])
+            .unwrap();
+        assert_eq!(balance(&app, &listed, &peer), 25);
+
+        // The owner withdraws the token balance along with the native one
+        app.execute_contract(Addr::unchecked("member"), peer.clone(), &PeerExecMsg::Withdraw { amount: None, receiver: None }, &[])
+            .unwrap();
+        assert_eq!(balance(&app, &listed, &peer), 0);
+        assert_eq!(balance(&app, &listed, &Addr::unchecked("member")), 25);
+    }
+
+    #[test]
+    fn instantiate_validation() {
+        let mut app = App::default();
+        let peer_code_id = app.store_code(peer());
+        let manager_code_id = app.store_code(manager());
+
+        let msg = |peer_code_id, incremental_donation, collective_ratio| InstantiateMsg {
+            incremental_donation,
+            collective_ratio,
+            ..instantiate_msg(peer_code_id)
+        };
+        let mut instantiate = |msg: InstantiateMsg| -> ContractError {
+            app.instantiate_contract(manager_code_id, Addr::unchecked("admin"), &msg, &[], "manager", None)
+                .unwrap_err()
+                .downcast()
+                .unwrap()
+        };
+
+        assert_eq!(
+            instantiate(msg(peer_code_id, coin(100, "utgd"), Decimal::percent(150))),
+            ContractError::InvalidRatio {}
+        );
+        assert_eq!(
+            instantiate(msg(peer_code_id, coin(0, "utgd"), Decimal::percent(60))),
+            ContractError::InvalidIncrement {}
+        );
+        assert_eq!(
+            instantiate(msg(peer_code_id, coin(100, ""), Decimal::percent(60))),
+            ContractError::InvalidIncrement {}
+        );
+        assert_eq!(
+            instantiate(msg(999, coin(100, "utgd"), Decimal::percent(60))),
+            ContractError::UnknownCodeId { code_id: 999 }
+        );
+    }
+
+    fn pending_of(app: &App, manager: &Addr, peer: &Addr) -> Vec<Coin> {
+        let resp: PendingRewardsResp = app
+            .wrap()
+            .query_wasm_smart(manager, &QueryMsg::PendingRewards { peer: peer.to_string() })
+            .unwrap();
+        resp.rewards
+    }
+
+    #[test]
+    fn sqrt_then_equal_strategy() {
+        let mut app = App::new(|router, _api, storage| {
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked("donator"), coins(1100, "utgd"))
+                .unwrap();
+        });
+        let manager = setup_manager_with(&mut app, |msg| InstantiateMsg {
+            weight_strategy: WeightStrategy::Sqrt,
+            ..msg
+        });
+        let peer1 = join_as(&mut app, &manager, "member1");
+        let peer2 = join_as(&mut app, &manager, "member2");
+
+        // Weights 1 and 4 give shares 1:2. Forwarded 60 goes to peer1 alone, 240 is split 80/160, 300 is split 100/200
+        app.execute_contract(Addr::unchecked("donator"), peer1.clone(), &PeerExecMsg::Donate { memo: None, anonymous: None }, &coins(100, "utgd"))
+            .unwrap();
+        app.execute_contract(Addr::unchecked("donator"), peer2.clone(), &PeerExecMsg::Donate { memo: None, anonymous: None }, &coins(400, "utgd"))
+            .unwrap();
+        app.execute_contract(Addr::unchecked("donator"), manager.clone(), &ExecuteMsg::Donate {}, &coins(300, "utgd"))
+            .unwrap();
+        assert_eq!(pending_of(&app, &manager, &peer1), coins(240, "utgd"));
+        assert_eq!(pending_of(&app, &manager, &peer2), coins(360, "utgd"));
+
+        // Switching keeps what was earned so far, the next donation is split evenly
+        let update = |weight_strategy| ExecuteMsg::UpdateConfig(Box::new(ConfigUpdate {
+            weight_strategy: Some(weight_strategy),
+            ..Default::default()
+        }));
+        let err = app
+            .execute_contract(Addr::unchecked("admin"), manager.clone(), &update(WeightStrategy::CappedLinear { max_share: Decimal::zero() }), &[])
+            .unwrap_err();
+        assert_eq!(ContractError::InvalidShare {}, err.downcast().unwrap());
+
+        app.execute_contract(Addr::unchecked("admin"), manager.clone(), &update(WeightStrategy::Equal), &[])
+            .unwrap();
+        app.execute_contract(Addr::unchecked("donator"), manager.clone(), &ExecuteMsg::Donate {}, &coins(300, "utgd"))
+            .unwrap();
+        assert_eq!(pending_of(&app, &manager, &peer1), coins(390, "utgd"));
+        assert_eq!(pending_of(&app, &manager, &peer2), coins(510, "utgd"));
+    }
+
+    #[test]
+    fn strategy_switch_moves_members_in_pages() {
+        let mut app = App::new(|router, _api, storage| {
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked("donator"), coins(340, "utgd"))
+                .unwrap();
+        });
+        let manager = setup_manager_with(&mut app, |msg| InstantiateMsg {
+            weight_strategy: WeightStrategy::Linear,
+            ..msg
+        });
+        let peers: Vec<_> = (0..12)
+            .map(|i| join_as(&mut app, &manager, &format!("member{i}")))
+            .collect();
+        app.execute_contract(Addr::unchecked("donator"), peers[0].clone(), &PeerExecMsg::Donate { memo: None, anonymous: None }, &coins(100, "utgd"))
+            .unwrap();
+        assert_eq!(pending_of(&app, &manager, &peers[0]), coins(60, "utgd"));
+
+        let attr = |resp: &cw_multi_test::AppResponse, key: &str| {
+            resp.events
+                .iter()
+                .flat_map(|event| &event.attributes)
+                .find(|attr| attr.key == key)
+                .map(|attr| attr.value.clone())
+        };
+        let update = |weight_strategy| ExecuteMsg::UpdateConfig(Box::new(ConfigUpdate {
+            weight_strategy: Some(weight_strategy),
+            ..Default::default()
+        }));
+        let resp = app
+            .execute_contract(Addr::unchecked("admin"), manager.clone(), &update(WeightStrategy::Equal), &[])
+            .unwrap();
+        assert_eq!(attr(&resp, "moved"), Some("10".to_string()));
+        assert_eq!(attr(&resp, "done"), Some("false".to_string()));
+
+        // Until every member is moved a second switch is refused and donations are held
+        let err = app
+            .execute_contract(Addr::unchecked("admin"), manager.clone(), &update(WeightStrategy::Sqrt), &[])
+            .unwrap_err();
+        assert_eq!(ContractError::ResettleRunning {}, err.downcast().unwrap());
+        app.execute_contract(Addr::unchecked("donator"), manager.clone(), &ExecuteMsg::Donate {}, &coins(120, "utgd"))
+            .unwrap();
+        assert_eq!(held_of(&app, &manager, "utgd"), Uint128::new(120));
+        assert_eq!(pending_of(&app, &manager, &peers[0]), coins(60, "utgd"));
+
+        let resp = app
+            .execute_contract(Addr::unchecked("anyone"), manager.clone(), &ExecuteMsg::Resettle { limit: None }, &[])
+            .unwrap();
+        assert_eq!(attr(&resp, "moved"), Some("2".to_string()));
+        assert_eq!(attr(&resp, "done"), Some("true".to_string()));
+        let err = app
+            .execute_contract(Addr::unchecked("anyone"), manager.clone(), &ExecuteMsg::Resettle { limit: None }, &[])
+            .unwrap_err();
+        assert_eq!(ContractError::NoResettle {}, err.downcast().unwrap());
+
+        // The held donation goes out with the next one, split evenly
+        app.execute_contract(Addr::unchecked("donator"), manager.clone(), &ExecuteMsg::Donate {}, &coins(120, "utgd"))
+            .unwrap();
+        assert_eq!(held_of(&app, &manager, "utgd"), Uint128::zero());
+        assert_eq!(pending_of(&app, &manager, &peers[0]), coins(80, "utgd"));
+        assert_eq!(pending_of(&app, &manager, &peers[11]), coins(20, "utgd"));
+    }
+
+    #[test]
+    fn capped_strategy_is_bounded() {
+        let mut app = App::default();
+        let manager = setup_manager(&mut app);
+        for i in 0..=MAX_CAPPED_MEMBERS {
+            join_as(&mut app, &manager, &format!("member{i}"));
+        }
+
+        let capped = ExecuteMsg::UpdateConfig(Box::new(ConfigUpdate {
+            weight_strategy: Some(WeightStrategy::CappedLinear { max_share: Decimal::percent(50) }),
+            ..Default::default()
+        }));
+        let err = app
+            .execute_contract(Addr::unchecked("admin"), manager.clone(), &capped, &[])
+            .unwrap_err();
+        assert_eq!(ContractError::TooManyMembers { max: MAX_CAPPED_MEMBERS }, err.downcast().unwrap());
+
+        // Once a member leaves the strategy fits, but nobody else can join
+        app.execute_contract(Addr::unchecked("member0"), manager.clone(), &ExecuteMsg::Leave {}, &[])
+            .unwrap();
+        app.execute_contract(Addr::unchecked("admin"), manager.clone(), &capped, &[])
+            .unwrap();
+        let err = app
+            .execute_contract(Addr::unchecked("member0"), manager.clone(), &ExecuteMsg::Join {}, &[])
+            .unwrap_err();
+        assert_eq!(ContractError::TooManyMembers { max: MAX_CAPPED_MEMBERS }, err.downcast().unwrap());
+    }
+
+    #[test]
+    fn capped_strategy_with_floor() {
+        let mut app = App::new(|router, _api, storage| {
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked("donator"), coins(100, "utgd"))
+                .unwrap();
+        });
+        let manager = setup_manager_with(&mut app, |msg| InstantiateMsg {
+            weight_strategy: WeightStrategy::CappedLinear { max_share: Decimal::percent(50) },
+            floor_share: Decimal::percent(20),
+            ..msg
+        });
+        let peers: Vec<_> = ["member1", "member2", "member3"]
+            .into_iter()
+            .map(|owner| join_as(&mut app, &manager, owner))
+            .collect();
+
+        // Of the forwarded 60, 12 is the floor split by everybody. Peer1 has all the weight but is capped at half
+        // of the remaining 48, the other half goes to the peers without weight and nothing is held
+        app.execute_contract(Addr::unchecked("donator"), peers[0].clone(), &PeerExecMsg::Donate { memo: None, anonymous: None }, &coins(100, "utgd"))
+            .unwrap();
+        assert_eq!(pending_of(&app, &manager, &peers[0]), coins(28, "utgd"));
+        assert_eq!(pending_of(&app, &manager, &peers[1]), coins(16, "utgd"));
+        assert_eq!(pending_of(&app, &manager, &peers[2]), coins(16, "utgd"));
+        assert_eq!(held_of(&app, &manager, "utgd"), Uint128::zero());
+    }
+
+    #[test]
+    fn quadratic_funding_round() {
+        let mut app = App::new(|router, _api, storage| {
+            for (addr, amount) in [("admin", 601), ("sponsor", 400), ("alice", 200), ("bob", 100), ("carol", 100), ("dave", 900)] {
+                router
+                    .bank
+                    .init_balance(storage, &Addr::unchecked(addr), coins(amount, "utgd"))
+                    .unwrap();
+            }
+        });
+        let manager = setup_manager(&mut app);
+        let peer1 = join_as(&mut app, &manager, "member1");
+        let peer2 = join_as(&mut app, &manager, "member2");
+        let donate = |app: &mut App, donor: &str, peer: &Addr, amount, anonymous| {
+            app.execute_contract(Addr::unchecked(donor), peer.clone(), &PeerExecMsg::Donate { memo: None, anonymous: Some(anonymous) }, &coins(amount, "utgd"))
+                .unwrap();
+        };
+
+        let now = app.block_info().time;
+        let err = app
+            .execute_contract(Addr::unchecked("admin"), manager.clone(), &ExecuteMsg::StartRound { end: now }, &coins(600, "utgd"))
+            .unwrap_err();
+        assert_eq!(ContractError::InvalidRoundEnd {}, err.downcast().unwrap());
+        app.execute_contract(Addr::unchecked("admin"), manager.clone(), &ExecuteMsg::StartRound { end: now.plus_seconds(100) }, &coins(600, "utgd"))
+            .unwrap();
+        app.execute_contract(Addr::unchecked("sponsor"), manager.clone(), &ExecuteMsg::FundRound {}, &coins(400, "utgd"))
+            .unwrap();
+        let round: RoundResp = app.wrap().query_wasm_smart(manager.clone(), &QueryMsg::Round {}).unwrap();
+        assert_eq!(round.round.unwrap().pool, Uint128::new(1000));
+
+        // Two donors of 100 weigh (10 + 10)^2 = 400, a single one 100. Anonymous donations are not matched
+        donate(&mut app, "alice", &peer1, 100, false);
+        donate(&mut app, "bob", &peer1, 100, false);
+        donate(&mut app, "carol", &peer2, 100, false);
+        donate(&mut app, "dave", &peer2, 900, true);
+        let round_match = |app: &App, peer: &Addr| -> RoundMatchResp {
+            app.wrap()
+                .query_wasm_smart(manager.clone(), &QueryMsg::RoundMatch { id: 1, peer: peer.to_string() })
+                .unwrap()
+        };
+        assert_eq!(round_match(&app, &peer1), RoundMatchResp { quadratic_weight: Uint128::new(400), matched: None });
+        assert_eq!(round_match(&app, &peer2), RoundMatchResp { quadratic_weight: Uint128::new(100), matched: None });
+
+        let end_round = |app: &mut App, limit| {
+            app.execute_contract(Addr::unchecked("anyone"), manager.clone(), &ExecuteMsg::EndRound { limit }, &[])
+        };
+        let err = end_round(&mut app, None).unwrap_err();
+        assert_eq!(ContractError::RoundNotOver {}, err.downcast().unwrap());
+
+        // Donations after the end are not matched anymore
+        app.update_block(|block| block.time = block.time.plus_seconds(100));
+        donate(&mut app, "alice", &peer2, 100, false);
+        let before = [pending_of(&app, &manager, &peer1), pending_of(&app, &manager, &peer2)];
+        let err = app
+            .execute_contract(Addr::unchecked("admin"), manager.clone(), &ExecuteMsg::EndRound { limit: None }, &coins(1, "utgd"))
+            .unwrap_err();
+        assert_eq!(ContractError::Payment(PaymentError::NonPayable {}), err.downcast().unwrap());
+
+        // One peer at a time: both are visited to add up the weights and again to be paid, an empty page
+        // ends each pass. No new round starts before the matches are paid out
+        let done = |resp: cw_multi_test::AppResponse| {
+            resp.events
+                .iter()
+                .flat_map(|event| &event.attributes)
+                .any(|attr| attr.key == "done" && attr.value == "true")
+        };
+        for _ in 0..4 {
+            assert!(!done(end_round(&mut app, Some(1)).unwrap()));
+        }
+        let err = app
+            .execute_contract(Addr::unchecked("admin"), manager.clone(), &ExecuteMsg::StartRound { end: now.plus_seconds(1000) }, &coins(1, "utgd"))
+            .unwrap_err();
+        assert_eq!(ContractError::RoundRunning {}, err.downcast().unwrap());
+        assert!(done(end_round(&mut app, Some(1)).unwrap()));
+        assert_eq!(pending_of(&app, &manager, &peer1)[0].amount - before[0][0].amount, Uint128::new(800));
+        assert_eq!(pending_of(&app, &manager, &peer2)[0].amount - before[1][0].amount, Uint128::new(200));
+        assert_eq!(round_match(&app, &peer1).matched, Some(Uint128::new(800)));
+
+        let round: RoundResp = app.wrap().query_wasm_smart(manager.clone(), &QueryMsg::Round {}).unwrap();
+        assert_eq!(round.round, None);
+        let err = app
+            .execute_contract(Addr::unchecked("sponsor"), manager, &ExecuteMsg::FundRound {}, &[])
+            .unwrap_err();
+        assert_eq!(ContractError::NoRound {}, err.downcast().unwrap());
+    }
+
+    #[test]
+    fn epochs_use_snapshotted_weights() {
+        let mut app = App::new(|router, _api, storage| {
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked("donator"), coins(1000, "utgd"))
+                .unwrap();
+        });
+        let manager = setup_manager(&mut app);
+        let peer1 = join_as(&mut app, &manager, "member1");
+        let peer2 = join_as(&mut app, &manager, "member2");
+        let update = ExecuteMsg::UpdateConfig(Box::new(ConfigUpdate {
+            epoch_length: Some(100),
+            ..Default::default()
+        }));
+        app.execute_contract(Addr::unchecked("admin"), manager.clone(), &update, &[])
+            .unwrap();
+        let donate = |app: &mut App, peer: &Addr, amount| {
+            app.execute_contract(Addr::unchecked("donator"), peer.clone(), &PeerExecMsg::Donate { memo: None, anonymous: None }, &coins(amount, "utgd"))
+                .unwrap();
+        };
+        let distribute = |app: &mut App| {
+            app.execute_contract(Addr::unchecked("anyone"), manager.clone(), &ExecuteMsg::Distribute { limit: None }, &[])
+        };
+        let epoch = |app: &App, id| -> EpochResp {
+            app.wrap().query_wasm_smart(manager.clone(), &QueryMsg::Epoch { id, start_after: None, limit: None }).unwrap()
+        };
+
+        // Epoch 1 starts before anyone has weight, its 60 is held
+        app.update_block(next_block);
+        donate(&mut app, &peer1, 100);
+        let err = distribute(&mut app).unwrap_err();
+        assert_eq!(ContractError::EpochNotOver {}, err.downcast().unwrap());
+
+        // Epoch 2 starts with peer1 alone having weight. Pumping peer2 during the epoch does not help it
+        app.update_block(|block| {
+            block.height += 1;
+            block.time = block.time.plus_seconds(100);
+        });
+        donate(&mut app, &peer2, 300);
+        app.execute_contract(Addr::unchecked("donator"), manager.clone(), &ExecuteMsg::Donate {}, &coins(200, "utgd"))
+            .unwrap();
+        assert_eq!(epoch(&app, 2).epoch.funds, coins(380, "utgd"));
+
+        distribute(&mut app).unwrap();
+        let resp = epoch(&app, 1);
+        assert!(resp.epoch.distributed);
+        assert_eq!(resp.payouts, vec![]);
+        let err = distribute(&mut app).unwrap_err();
+        assert_eq!(ContractError::EpochNotOver {}, err.downcast().unwrap());
+
+        app.update_block(|block| {
+            block.height += 1;
+            block.time = block.time.plus_seconds(100);
+        });
+        // Leaving before the ended epoch is paid out would lose the share of peer1
+        let err = app
+            .execute_contract(Addr::unchecked("member1"), manager.clone(), &ExecuteMsg::Leave {}, &[])
+            .unwrap_err();
+        assert_eq!(ContractError::UndistributedEpoch { id: 2 }, err.downcast().unwrap());
+
+        distribute(&mut app).unwrap();
+        assert_eq!(epoch(&app, 2).payouts, vec![EpochPayout { peer: peer1.clone(), amount: coins(440, "utgd") }]);
+        assert_eq!(pending_of(&app, &manager, &peer1), coins(440, "utgd"));
+        assert_eq!(pending_of(&app, &manager, &peer2), vec![]);
+        app.execute_contract(Addr::unchecked("member1"), manager.clone(), &ExecuteMsg::Leave {}, &[])
+            .unwrap();
+        assert_eq!(app.wrap().query_balance(&peer1, "utgd").unwrap().amount.u128(), 40 + 440);
+
+        let err = distribute(&mut app).unwrap_err();
+        assert_eq!(ContractError::NoEpoch {}, err.downcast().unwrap());
+    }
+
+    #[test]
+    fn epochs_keep_their_split_and_pay_out_in_pages() {
+        let mut app = App::new(|router, _api, storage| {
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked("donator"), coins(801, "utgd"))
+                .unwrap();
+        });
+        let manager = setup_manager(&mut app);
+        let peers: Vec<_> = ["member1", "member2", "member3"]
+            .into_iter()
+            .map(|owner| join_as(&mut app, &manager, owner))
+            .collect();
+        let update = |update| ExecuteMsg::UpdateConfig(Box::new(update));
+        app.execute_contract(Addr::unchecked("admin"), manager.clone(), &update(ConfigUpdate { epoch_length: Some(100), ..Default::default() }), &[])
+            .unwrap();
+        let next_epoch = |app: &mut App| {
+            app.update_block(|block| {
+                block.height += 1;
+                block.time = block.time.plus_seconds(100);
+            });
+        };
+        // Pays out the oldest epoch one member at a time, and returns the number of calls it took
+        let distribute = |app: &mut App| {
+            let mut calls = 0;
+            loop {
+                let resp = app
+                    .execute_contract(Addr::unchecked("anyone"), manager.clone(), &ExecuteMsg::Distribute { limit: Some(1) }, &[])
+                    .unwrap();
+                calls += 1;
+                let done = resp
+                    .events
+                    .iter()
+                    .flat_map(|event| &event.attributes)
+                    .any(|attr| attr.key == "done" && attr.value == "true");
+                if done {
+                    return calls;
+                }
+            }
+        };
+
+        // Weights 1 and 3 are donated during epoch 1, nobody has weight at its start so its 240 is held
+        app.update_block(next_block);
+        for (peer, amount) in [(&peers[0], 100), (&peers[1], 300)] {
+            app.execute_contract(Addr::unchecked("donator"), peer.clone(), &PeerExecMsg::Donate { memo: None, anonymous: None }, &coins(amount, "utgd"))
+                .unwrap();
+        }
+
+        // Epoch 2 opens with the linear strategy. Switching to equal parts with a floor does not change it
+        next_epoch(&mut app);
+        app.execute_contract(Addr::unchecked("donator"), manager.clone(), &ExecuteMsg::Donate {}, &coins(400, "utgd"))
+            .unwrap();
+        let equal = ConfigUpdate {
+            weight_strategy: Some(WeightStrategy::Equal),
+            floor_share: Some(Decimal::percent(50)),
+            ..Default::default()
+        };
+        app.execute_contract(Addr::unchecked("admin"), manager.clone(), &update(equal), &[])
+            .unwrap();
+
+        // Every member is visited once to add up the shares and once to be paid, an empty page ends each pass
+        next_epoch(&mut app);
+        assert_eq!(distribute(&mut app), 7);
+        assert_eq!(held_of(&app, &manager, "utgd"), Uint128::new(240));
+        assert_eq!(distribute(&mut app), 7);
+        assert_eq!(pending_of(&app, &manager, &peers[0]), coins(160, "utgd"));
+        assert_eq!(pending_of(&app, &manager, &peers[1]), coins(480, "utgd"));
+        assert_eq!(pending_of(&app, &manager, &peers[2]), vec![]);
+        assert_eq!(held_of(&app, &manager, "utgd"), Uint128::zero());
+
+        let epoch = |start_after: Option<&Addr>| -> EpochResp {
+            let query = QueryMsg::Epoch { id: 2, start_after: start_after.map(|peer| peer.to_string()), limit: Some(1) };
+            app.wrap().query_wasm_smart(manager.clone(), &query).unwrap()
+        };
+        let first = epoch(None);
+        assert!(first.epoch.distributed);
+        assert_eq!(first.epoch.weight_strategy, WeightStrategy::Linear);
+        assert_eq!(first.payouts.len(), 1);
+        let second = epoch(Some(&first.payouts[0].peer));
+        assert_eq!(second.payouts.len(), 1);
+        assert_ne!(first.payouts[0].peer, second.payouts[0].peer);
+        assert_eq!(epoch(Some(&second.payouts[0].peer)).payouts, vec![]);
+
+        let err = app
+            .execute_contract(Addr::unchecked("donator"), manager.clone(), &ExecuteMsg::Distribute { limit: None }, &coins(1, "utgd"))
+            .unwrap_err();
+        assert_eq!(ContractError::Payment(PaymentError::NonPayable {}), err.downcast().unwrap());
+    }
+
+    #[test]
+    fn decayed_weights_are_synced() {
+        let mut app = App::new(|router, _api, storage| {
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked("donator"), coins(400, "utgd"))
+                .unwrap();
+        });
+        let manager = setup_manager(&mut app);
+        let peer = join_as(&mut app, &manager, "member");
+        app.execute_contract(Addr::unchecked("donator"), peer.clone(), &PeerExecMsg::Donate { memo: None, anonymous: None }, &coins(400, "utgd"))
+            .unwrap();
+
+        let update = ExecuteMsg::UpdateConfig(Box::new(ConfigUpdate {
+            decay: Some(Decay::Exponential { half_life: Duration::Time(100) }),
+            ..Default::default()
+        }));
+        app.execute_contract(Addr::unchecked("admin"), manager.clone(), &update, &[])
+            .unwrap();
+        app.execute_contract(Addr::unchecked("admin"), manager.clone(), &ExecuteMsg::PropagateConfig { start_after: None, limit: None }, &[])
+            .unwrap();
+
+        let weight = |app: &App| -> u64 {
+            let resp: MembersResp = app
+                .wrap()
+                .query_wasm_smart(manager.clone(), &QueryMsg::Members { start_after: None, limit: None })
+                .unwrap();
+            resp.members[0].donators
+        };
+        let sync = ExecuteMsg::SyncWeights { start_after: None, limit: None };
+        app.update_block(|block| block.time = block.time.plus_seconds(200));
+        let resp = app.execute_contract(Addr::unchecked("anyone"), manager.clone(), &sync, &[])
+            .unwrap();
+        let attr = |key| resp.events.iter().flat_map(|e| &e.attributes).find(|attr| attr.key == key).unwrap().value.clone();
+        assert_eq!(attr("synced"), "1");
+        assert_eq!(attr("last"), peer.to_string());
+        assert_eq!(weight(&app), 1);
+
+        // The peer can still push its own weight
+        app.update_block(|block| block.time = block.time.plus_seconds(200));
+        app.execute_contract(Addr::unchecked("anyone"), peer, &PeerExecMsg::SyncWeight {}, &[])
+            .unwrap();
+        assert_eq!(weight(&app), 0);
+    }
+
+    #[test]
+    fn weight_blends_units_and_unique_donors() {
+        let mut app = App::new(|router, _api, storage| {
+            for (donator, amount) in [("alice", 500), ("bob", 100), ("carol", 100)] {
+                router
+                    .bank
+                    .init_balance(storage, &Addr::unchecked(donator), coins(amount, "utgd"))
+                    .unwrap();
+            }
+        });
+        let manager = setup_manager(&mut app);
+        let first = join_as(&mut app, &manager, "first");
+        let second = join_as(&mut app, &manager, "second");
+        let donate = PeerExecMsg::Donate { memo: None, anonymous: None };
+        app.execute_contract(Addr::unchecked("alice"), first, &donate, &coins(500, "utgd"))
+            .unwrap();
+        for donator in ["bob", "carol"] {
+            app.execute_contract(Addr::unchecked(donator), second.clone(), &donate, &coins(100, "utgd"))
+                .unwrap();
+        }
+
+        let weights = |app: &App| -> Vec<(String, u64, u64, u64)> {
+            let resp: MembersResp = app
+                .wrap()
+                .query_wasm_smart(manager.clone(), &QueryMsg::Members { start_after: None, limit: None })
+                .unwrap();
+            let mut weights: Vec<_> = resp
+                .members
+                .into_iter()
+                .map(|member| (member.owner.to_string(), member.donators, member.units, member.unique_donors))
+                .collect();
+            weights.sort();
+            weights
+        };
+        let set_share = |app: &mut App, share: Decimal| {
+            let update = ExecuteMsg::UpdateConfig(Box::new(ConfigUpdate {
+                donor_share: Some(share),
+                ..Default::default()
+            }));
+            app.execute_contract(Addr::unchecked("admin"), manager.clone(), &update, &[])
+        };
+
+        // Units only by default
+        assert_eq!(weights(&app), vec![("first".to_string(), 5, 5, 1), ("second".to_string(), 2, 2, 2)]);
+
+        set_share(&mut app, Decimal::one()).unwrap();
+        assert_eq!(weights(&app), vec![("first".to_string(), 1, 5, 1), ("second".to_string(), 2, 2, 2)]);
+
+        set_share(&mut app, Decimal::percent(50)).unwrap();
+        assert_eq!(weights(&app), vec![("first".to_string(), 3, 5, 1), ("second".to_string(), 2, 2, 2)]);
+
+        let err = set_share(&mut app, Decimal::percent(150)).unwrap_err();
+        assert_eq!(ContractError::InvalidShare {}, err.downcast().unwrap());
+
+        // The donor count would never fade with the units
+        let update = ExecuteMsg::UpdateConfig(Box::new(ConfigUpdate {
+            decay: Some(Decay::Linear { half_life: Duration::Height(100) }),
+            ..Default::default()
+        }));
+        let err = app
+            .execute_contract(Addr::unchecked("admin"), manager.clone(), &update, &[])
+            .unwrap_err();
+        assert_eq!(ContractError::DonorShareWithDecay {}, err.downcast().unwrap());
+    }
+
+    #[test]
+    fn migrate_checks_version() {
+        let mut deps = mock_dependencies();
+        set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "0.0.1").unwrap();
+
+        let resp = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+        assert!(resp.attributes.iter().any(|attr| attr.key == "from_version" && attr.value == "0.0.1"));
+        assert_eq!(get_contract_version(&deps.storage).unwrap().version, CONTRACT_VERSION);
+
+        set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "not-a-version").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+        assert_eq!(ContractError::InvalidVersion { version: "not-a-version".to_string() }, err);
+
+        // A peer cannot be migrated into a manager
+        set_contract_version(deps.as_mut().storage, "crates.io:donation-peer", CONTRACT_VERSION).unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+        assert_eq!(ContractError::WrongContract {
+            expected: CONTRACT_NAME.to_string(),
+            found: "crates.io:donation-peer".to_string(),
+        }, err);
+    }
+
+    #[test]
+    fn migrate_converts_legacy_managers() {
+        let mut deps = mock_dependencies();
+        deps.querier.update_wasm(|query| match query {
+            WasmQuery::ContractInfo { .. } => {
+                let mut info = ContractInfoResponse::default();
+                info.admin = Some("admin".to_string());
+                SystemResult::Ok(ContractResult::Ok(to_json_binary(&info).unwrap()))
+            }
+            _ => unimplemented!(),
+        });
+        let legacy = LegacyConfig {
+            peer_code_id: 1,
+            incremental_donation: coin(10, "utgd"),
+            collective_ratio: Decimal::percent(60),
+        };
+        LEGACY_CONFIG.save(deps.as_mut().storage, &legacy).unwrap();
+        for (peer, owner) in [("peer1", "owner1"), ("peer2", "owner2"), ("peer3", "owner1")] {
+            LEGACY_MEMBERS.save(deps.as_mut().storage, &Addr::unchecked(peer), &Addr::unchecked(owner)).unwrap();
+        }
+        LEGACY_PENDING_INSTANTIATION.save(deps.as_mut().storage, &Addr::unchecked("owner2")).unwrap();
+
+        // Without a recorded version the manager is taken to be a legacy one
+        let resp = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+        let attr = |key| resp.attributes.iter().find(|attr| attr.key == key).unwrap().value.clone();
+        assert_eq!(attr("from_version"), "legacy");
+        assert_eq!(attr("dropped"), "peer3");
+        assert_eq!(get_contract_version(&deps.storage).unwrap().version, CONTRACT_VERSION);
+
+        let config = CONFIG.load(&deps.storage).unwrap();
+        assert_eq!((config.peer_code_id, config.other_denoms), (1, DenomPolicy::PassThrough));
+        assert_eq!(ADMIN.load(&deps.storage).unwrap(), Some(Addr::unchecked("admin")));
+        assert_eq!(MEMBER_COUNT.load(&deps.storage).unwrap(), 2);
+        assert_eq!(peer_of(&deps.storage, &Addr::unchecked("owner1")).unwrap(), Some(Addr::unchecked("peer1")));
+        assert_eq!(peer_of(&deps.storage, &Addr::unchecked("owner2")).unwrap(), Some(Addr::unchecked("peer2")));
+        assert!(!members().has(&deps.storage, Addr::unchecked("peer3")));
+        assert_eq!(TOTAL_SHARES.load(&deps.storage).unwrap(), Uint128::zero());
+        assert!(LEGACY_PENDING_INSTANTIATION.may_load(&deps.storage).unwrap().is_none());
+    }
 }
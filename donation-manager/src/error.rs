@@ -1,11 +1,137 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{ConversionOverflowError, Instantiate2AddressError, OverflowError, StdError};
+use cw_utils::{ParseReplyError, PaymentError};
 use thiserror::Error;
 
-#[derive(Error, Debug)]
+use donation_peer::helpers::MigrateError;
+use donation_peer::state::IncrementError;
+
+#[derive(Error, Debug, PartialEq)]
 pub enum ContractError {
     #[error("{0}")]
     Std(#[from] StdError),
 
-    #[error("Custom Error val: {val:?}")]
-    CustomError { val: String },
+    #[error("{0}")]
+    Overflow(#[from] OverflowError),
+
+    #[error("{0}")]
+    ConversionOverflow(#[from] ConversionOverflowError),
+
+    #[error("{0}")]
+    Payment(#[from] PaymentError),
+
+    #[error("Unauthorized")]
+    Unauthorized {},
+
+    #[error("Sender already owns a peer")]
+    AlreadyMember {},
+
+    #[error("Sender is not a member of the collective")]
+    NotMember {},
+
+    #[error("No funds sent with the donation")]
+    NoFunds {},
+
+    #[error("Donations in {denom} are not accepted")]
+    InvalidDenom { denom: String },
+
+    #[error("No rewards to claim")]
+    NothingToClaim {},
+
+    #[error("Cannot split funds between members without any weight")]
+    ZeroWeight {},
+
+    #[error("Collective ratio has to be between 0 and 1")]
+    InvalidRatio {},
+
+    #[error("Shares have to be between 0 and 1, and the cap of a share above 0")]
+    InvalidShare {},
+
+    #[error("The capped strategy supports at most {max} members")]
+    TooManyMembers { max: u64 },
+
+    #[error("Half life has to be above 0 and at most 100 years")]
+    InvalidHalfLife {},
+
+    #[error("Unique donors do not decay, the donor share has to be 0 with a decay")]
+    DonorShareWithDecay {},
+
+    #[error("Incremental donation has to be a non-zero amount of a denom")]
+    InvalidIncrement {},
+
+    #[error("Denom {denom} is listed more than once")]
+    DuplicateDenom { denom: String },
+
+    #[error("A matching round is already running")]
+    RoundRunning {},
+
+    #[error("No matching round is running")]
+    NoRound {},
+
+    #[error("The matching round has to end in the future")]
+    InvalidRoundEnd {},
+
+    #[error("The matching round is over")]
+    RoundOver {},
+
+    #[error("The matching round is not over yet")]
+    RoundNotOver {},
+
+    #[error("Members are still being moved to the shares of the last config change")]
+    ResettleRunning {},
+
+    #[error("No members are waiting to be moved to new shares")]
+    NoResettle {},
+
+    #[error("No epoch to distribute")]
+    NoEpoch {},
+
+    #[error("The epoch is not over yet")]
+    EpochNotOver {},
+
+    #[error("Epoch {id} owes the peer a share, distribute it before leaving")]
+    UndistributedEpoch { id: u64 },
+
+    #[error("No code stored with id {code_id}")]
+    UnknownCodeId { code_id: u64 },
+
+    #[error("Peer addresses are not predictable with the current config")]
+    NotPredictable {},
+
+    #[error("{0}")]
+    Instantiate2Address(#[from] Instantiate2AddressError),
+
+    #[error("Unknown reply id {0}")]
+    UnknownReplyId(u64),
+
+    #[error("Invalid peer instantiation reply: {0}")]
+    InstantiateReplyParse(#[from] ParseReplyError),
+
+    #[error("Invalid contract version {version}")]
+    InvalidVersion { version: String },
+
+    #[error("Cannot migrate from {found} contract, expected {expected}")]
+    WrongContract { expected: String, found: String },
+
+    #[error("Cannot downgrade from version {from} to {to}")]
+    Downgrade { from: String, to: String },
+}
+
+impl From<IncrementError> for ContractError {
+    fn from(err: IncrementError) -> Self {
+        match err {
+            IncrementError::Invalid => ContractError::InvalidIncrement {},
+            IncrementError::Duplicate(denom) => ContractError::DuplicateDenom { denom },
+        }
+    }
+}
+
+impl From<MigrateError> for ContractError {
+    fn from(err: MigrateError) -> Self {
+        match err {
+            MigrateError::Std(err) => ContractError::Std(err),
+            MigrateError::InvalidVersion(version) => ContractError::InvalidVersion { version },
+            MigrateError::WrongContract { expected, found } => ContractError::WrongContract { expected, found },
+            MigrateError::Downgrade { from, to } => ContractError::Downgrade { from, to },
+        }
+    }
 }
@@ -1,4 +1,153 @@
+use cosmwasm_std::{Coin, StdResult, Uint128, Uint256};
 
+use crate::error::ContractError;
 
+// Adds `coin` to the coin of the same denom in `coins`, or appends it if there is none
+pub fn add_coin(coins: &mut Vec<Coin>, coin: Coin) -> StdResult<()> {
+    match coins.iter_mut().find(|existing| existing.denom == coin.denom) {
+        Some(existing) => existing.amount = existing.amount.checked_add(coin.amount)?,
+        None => coins.push(coin),
+    }
+    Ok(())
+}
 
+// Splits `amount` proportionally to `weights` so that the shares add up exactly to `amount`.
+// Every share is first rounded down, then the leftover dust goes one unit at a time to the entries
+// with the largest remainders (largest remainder method). Ties go to the entry which comes first,
+// so the result is deterministic. Weights adding up to zero are rejected with `ZeroWeight`.
+pub fn split_by_weight(amount: Uint128, weights: &[u128]) -> Result<Vec<Uint128>, ContractError> {
+    let total = weights
+        .iter()
+        .try_fold(Uint256::zero(), |total, weight| total.checked_add(Uint256::from(*weight)))?;
+    if total.is_zero() {
+        return Err(ContractError::ZeroWeight {});
+    }
 
+    let mut shares = Vec::with_capacity(weights.len());
+    let mut remainders = Vec::with_capacity(weights.len());
+    let mut assigned = Uint128::zero();
+    for (idx, weight) in weights.iter().enumerate() {
+        // Full 256-bit product, it cannot overflow and the share is never above `amount`
+        let product = amount.full_mul(*weight);
+        let share = Uint128::try_from(product / total)?;
+        remainders.push((product % total, idx));
+        assigned = assigned.checked_add(share)?;
+        shares.push(share);
+    }
+
+    // The dust is below the number of entries as every share lost less than one unit
+    let dust = amount.checked_sub(assigned)?.u128() as usize;
+    remainders.sort_by(|(rem1, idx1), (rem2, idx2)| rem2.cmp(rem1).then(idx1.cmp(idx2)));
+    for (_, idx) in remainders.into_iter().take(dust) {
+        shares[idx] += Uint128::new(1);
+    }
+
+    Ok(shares)
+}
+
+// Splits `amount` proportionally to `weights`, but no entry gets more than `cap`. What the capped entries
+// cannot take is split again between the others, until nobody is over the cap. Once every entry with weight
+// is capped the excess is split equally between the ones without, and once every entry is capped what is
+// left is split by weight on top of the cap, so nothing is left over.
+pub fn capped_split(amount: Uint128, weights: &[u128], cap: Uint128) -> Result<Vec<Uint128>, ContractError> {
+    let mut capped = vec![false; weights.len()];
+    loop {
+        let capped_count = capped.iter().filter(|capped| **capped).count() as u128;
+        let rest = amount.checked_sub(cap.checked_mul(Uint128::new(capped_count))?)?;
+
+        if capped_count == weights.len() as u128 {
+            let shares = split_by_weight(rest, weights)?
+                .into_iter()
+                .map(|share| share + cap)
+                .collect();
+            return Ok(shares);
+        }
+
+        let mut free: Vec<_> = weights
+            .iter()
+            .zip(&capped)
+            .map(|(weight, capped)| if *capped { 0 } else { *weight })
+            .collect();
+        if free.iter().all(|weight| *weight == 0) {
+            free = capped.iter().map(|capped| if *capped { 0 } else { 1 }).collect();
+        }
+        let shares = split_by_weight(rest, &free)?;
+
+        let over: Vec<_> = (0..weights.len()).filter(|idx| shares[*idx] > cap).collect();
+        if over.is_empty() {
+            let shares = shares
+                .into_iter()
+                .zip(&capped)
+                .map(|(share, capped)| if *capped { cap } else { share })
+                .collect();
+            return Ok(shares);
+        }
+        for idx in over {
+            capped[idx] = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(amount: u128, weights: &[u128]) -> Vec<u128> {
+        split_by_weight(Uint128::new(amount), weights)
+            .unwrap()
+            .into_iter()
+            .map(|share| share.u128())
+            .collect()
+    }
+
+    #[test]
+    fn exact_split() {
+        assert_eq!(split(100, &[1, 3]), vec![25, 75]);
+        assert_eq!(split(100, &[0, 5]), vec![0, 100]);
+    }
+
+    #[test]
+    fn dust_goes_to_largest_remainders() {
+        // 100/3 = 33.33 each, the single dust unit goes to the first entry on a tie
+        assert_eq!(split(100, &[1, 1, 1]), vec![34, 33, 33]);
+        // 10 * 1/6 = 1.67, 10 * 2/6 = 3.33, 10 * 3/6 = 5
+        assert_eq!(split(10, &[1, 2, 3]), vec![2, 3, 5]);
+        // Nothing to split between three
+        assert_eq!(split(2, &[1, 1, 1]), vec![1, 1, 0]);
+    }
+
+    #[test]
+    fn no_overflow_on_big_numbers() {
+        assert_eq!(split(u128::MAX, &[u128::MAX, u128::MAX]), vec![u128::MAX / 2 + 1, u128::MAX / 2]);
+    }
+
+    #[test]
+    fn zero_total_weight() {
+        assert_eq!(split_by_weight(Uint128::new(100), &[0, 0]).unwrap_err(), ContractError::ZeroWeight {});
+        assert_eq!(split_by_weight(Uint128::new(100), &[]).unwrap_err(), ContractError::ZeroWeight {});
+    }
+
+    fn capped(amount: u128, weights: &[u128], cap: u128) -> Vec<u128> {
+        capped_split(Uint128::new(amount), weights, Uint128::new(cap))
+            .unwrap()
+            .into_iter()
+            .map(|share| share.u128())
+            .collect()
+    }
+
+    #[test]
+    fn capped_split_moves_excess() {
+        // Nobody over the cap
+        assert_eq!(capped(100, &[1, 1], 50), vec![50, 50]);
+        // 80 is over the cap, the 30 above it goes to the others proportionally
+        assert_eq!(capped(100, &[8, 1, 1], 50), vec![50, 25, 25]);
+        // Capping one entry can push the next one over the cap
+        assert_eq!(capped(100, &[6, 3, 1], 40), vec![40, 40, 20]);
+        // Everyone with weight capped, the rest goes to the ones without
+        assert_eq!(capped(100, &[1, 1, 0], 40), vec![40, 40, 20]);
+        assert_eq!(capped(100, &[2, 0, 0], 50), vec![50, 25, 25]);
+        // Everyone capped, what is left is split by weight on top of the cap
+        assert_eq!(capped(100, &[1, 1, 0], 30), vec![35, 35, 30]);
+        assert_eq!(capped(100, &[3, 1], 10), vec![70, 30]);
+    }
+}
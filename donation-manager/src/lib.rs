@@ -2,6 +2,7 @@ pub mod contract;
 mod error;
 pub mod helpers;
 pub mod msg;
+pub mod rewards;
 pub mod state;
 
 pub use crate::error::ContractError;
@@ -1,7 +1,9 @@
-use cosmwasm_std::{Addr, Coin, Decimal};
+use cosmwasm_std::{Addr, Binary, Coin, Decimal, Timestamp, Uint128};
+use cw20::Cw20ReceiveMsg;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use crate::state::Config;
+use donation_peer::state::{Decay, DenomPolicy};
+use crate::state::{Config, Epoch, Round, WeightStrategy, ZeroWeightPolicy};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
@@ -9,6 +11,20 @@ pub struct InstantiateMsg {
     pub peer_code_id: u64,
     pub incremental_donation: Coin,
     pub collective_ratio: Decimal,
+    pub other_denoms: DenomPolicy,
+    pub zero_weight: ZeroWeightPolicy,
+    #[serde(default)]
+    pub predictable_addresses: bool,
+    #[serde(default)]
+    pub weight_strategy: WeightStrategy,
+    #[serde(default)]
+    pub floor_share: Decimal,
+    #[serde(default)]
+    pub epoch_length: u64,
+    #[serde(default)]
+    pub decay: Decay,
+    #[serde(default)]
+    pub donor_share: Decimal,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -17,13 +33,103 @@ pub enum ExecuteMsg {
     Join {},
     Leave {},
     Donate {},
+    // Donation in an allowlisted CW20 token, the hook message is a `ReceiveMsg`
+    Receive(Cw20ReceiveMsg),
+    // Sends the rewards accrued by the peer to it. Called by the peer or its owner
+    Claim {},
+    // Peer only. Reports the increments donated to the calling peer and its unique donors
+    UpdateWeight {
+        weight: u64,
+        #[serde(default)]
+        unique_donors: u64,
+    },
+    // Peer only. Reports a donation to the calling peer, counted by the running round if any
+    RecordDonation { donor: String, funds: Vec<Coin> },
+    // Admin only. Starts a matching round until `end`, the sent coin is the initial pool
+    StartRound { end: Timestamp },
+    // Adds the sent coins, in the denom of the round, to the pool of the running round
+    FundRound {},
+    // Distributes the pool of a round which is over between the peers. Anyone can call it
+    EndRound { limit: Option<u32> },
+    // Distributes the donations of the oldest epoch which is over, going through at most `limit` members.
+    // The next call goes on where the last one stopped until the epoch is paid out. Anyone can call it
+    Distribute { limit: Option<u32> },
+    // Admin only. Proposes a new admin, who has to accept it to take over
+    ProposeAdmin { admin: String },
+    // Proposed admin only
+    AcceptAdmin {},
+    // Admin only. Leaves the contract without an admin for good
+    RenounceAdmin {},
+    // Admin only. Fields which are not set are left unchanged. New peers pick the new values up
+    UpdateConfig(Box<ConfigUpdate>),
+    // Moves the next page of members, ordered by peer address, to their shares under a new weight strategy
+    // or donor share. Donations are held until every member is moved. Anyone can call it
+    Resettle { limit: Option<u32> },
+    // Takes the current weights of a page of peers, ordered by peer address, from the peers themselves.
+    // With decay the weights reported on donations fade, this brings them down before donations are split
+    // or an epoch takes its snapshot. Anyone can call it
+    SyncWeights {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    // Admin only. Sends the current config to a page of existing peers, ordered by peer address
+    PropagateConfig {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    // Admin only. Migrates a page of peers, ordered by peer address, to `new_code_id` with `msg`.
+    // New peers are instantiated from `new_code_id` from now on
+    MigratePeers {
+        new_code_id: u64,
+        msg: Binary,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ConfigUpdate {
+    pub peer_code_id: Option<u64>,
+    pub incremental_donation: Option<Coin>,
+    pub collective_ratio: Option<Decimal>,
+    pub other_denoms: Option<DenomPolicy>,
+    pub zero_weight: Option<ZeroWeightPolicy>,
+    pub predictable_addresses: Option<bool>,
+    pub weight_strategy: Option<WeightStrategy>,
+    pub floor_share: Option<Decimal>,
+    pub epoch_length: Option<u64>,
+    pub decay: Option<Decay>,
+    pub donor_share: Option<Decimal>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveMsg {
+    Donate {},
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
     Config {},
-    MemberPeerAddr { addr : String}
+    MemberPeerAddr { addr : String},
+    // Members ordered by peer address
+    Members {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    MemberCount {},
+    PendingRewards { peer: String },
+    Admin {},
+    // Address the next peer of `owner` gets, only with predictable addresses enabled
+    PredictPeerAddr { owner: String },
+    // The running matching round
+    Round {},
+    // Standing of `peer` in round `id`
+    RoundMatch { id: u64, peer: String },
+    // Epoch `id` with a page of the payouts of its peers
+    Epoch { id: u64, start_after: Option<String>, limit: Option<u32> },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -40,4 +146,77 @@ pub struct MemberPeerAddrResp {
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
-pub enum MigrateMsg {}
+pub struct PredictPeerAddrResp {
+    pub addr: Addr,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct MemberInfo {
+    pub peer: Addr,
+    pub owner: Addr,
+    // Weight of the peer, see `Config.donor_share`
+    pub donators: u64,
+    pub units: u64,
+    pub unique_donors: u64,
+    pub joined_height: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct MembersResp {
+    pub members: Vec<MemberInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct MemberCountResp {
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PendingRewardsResp {
+    pub rewards: Vec<Coin>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct AdminResp {
+    pub admin: Option<Addr>,
+    pub pending: Option<Addr>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct RoundResp {
+    pub round: Option<Round>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct RoundMatchResp {
+    // Square of the sum of the square roots of the contributions, the pool is split by it
+    pub quadratic_weight: Uint128,
+    // Only set once the round is ended
+    pub matched: Option<Uint128>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct EpochPayout {
+    pub peer: Addr,
+    pub amount: Vec<Coin>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct EpochResp {
+    pub epoch: Epoch,
+    // Ordered by peer address, empty until the epoch is distributed
+    pub payouts: Vec<EpochPayout>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct MigrateMsg {}
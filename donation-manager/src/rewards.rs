@@ -0,0 +1,152 @@
+use cosmwasm_std::{Addr, Coin, Decimal256, Order, StdResult, Storage, Uint128, Uint256};
+
+use crate::error::ContractError;
+use crate::state::{DUST, HELD, MEMBER_INDEX, PEER_REWARDS, PeerReward, REWARD_INDEX};
+
+// Rewards are accounted with a per-denom index of rewards per share. A donation only bumps
+// the index, and the part of every peer is computed lazily from the difference between the current
+// index and the one it was last settled at. The shares of a peer must not change without settling it
+// first, otherwise the new shares would be applied to the rewards accrued before.
+// Rewards split equally between the members work the same way, with a second index where every
+// member has exactly one share.
+
+// Adds `amount` split between `total_shares` to the index of its denom
+pub fn accrue(storage: &mut dyn Storage, amount: &Coin, total_shares: Uint128) -> Result<(), ContractError> {
+    if total_shares.is_zero() {
+        return Err(ContractError::ZeroWeight {});
+    }
+    let per_share = Decimal256::from_ratio(amount.amount, total_shares);
+    REWARD_INDEX.update(storage, &amount.denom, |index| -> StdResult<_> {
+        Ok(index.unwrap_or_default().checked_add(per_share)?)
+    })?;
+    Ok(())
+}
+
+// Adds `amount` split equally between `members` to the member index of its denom
+pub fn accrue_members(storage: &mut dyn Storage, amount: &Coin, members: u64) -> Result<(), ContractError> {
+    if members == 0 {
+        return Err(ContractError::ZeroWeight {});
+    }
+    let per_member = Decimal256::from_ratio(amount.amount, members);
+    known(storage, &amount.denom)?;
+    MEMBER_INDEX.update(storage, &amount.denom, |index| -> StdResult<_> {
+        Ok(index.unwrap_or_default().checked_add(per_member)?)
+    })?;
+    Ok(())
+}
+
+// Moves everything accrued by the peer since the last settlement to its pending rewards
+pub fn settle(storage: &mut dyn Storage, peer: &Addr, shares: Uint128) -> StdResult<()> {
+    let indexes = REWARD_INDEX
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    for (denom, index) in indexes {
+        // Denoms without an entry were first donated after the peer joined, so they start at zero
+        let reward = PEER_REWARDS.may_load(storage, (peer, &denom))?.unwrap_or_default();
+        let member_index = MEMBER_INDEX.may_load(storage, &denom)?.unwrap_or_default();
+        let reward = settled(reward, index, member_index, shares)?;
+        PEER_REWARDS.save(storage, (peer, &denom), &reward)?;
+    }
+
+    Ok(())
+}
+
+// Credits `amount` directly to the pending rewards of the peer, bypassing the index
+pub fn credit(storage: &mut dyn Storage, peer: &Addr, amount: &Coin) -> StdResult<()> {
+    known(storage, &amount.denom)?;
+
+    let mut reward = PEER_REWARDS.may_load(storage, (peer, &amount.denom))?.unwrap_or_default();
+    reward.pending = reward.pending.checked_add(Decimal256::from_ratio(amount.amount, 1u8))?;
+    PEER_REWARDS.save(storage, (peer, &amount.denom), &reward)
+}
+
+// Rewards the peer could claim right now, without the fractions
+pub fn pending(storage: &dyn Storage, peer: &Addr, shares: Uint128) -> StdResult<Vec<Coin>> {
+    let mut rewards = vec![];
+    for index in REWARD_INDEX.range(storage, None, None, Order::Ascending) {
+        let (denom, index) = index?;
+        let reward = PEER_REWARDS.may_load(storage, (peer, &denom))?.unwrap_or_default();
+        let member_index = MEMBER_INDEX.may_load(storage, &denom)?.unwrap_or_default();
+        let amount = whole(settled(reward, index, member_index, shares)?.pending)?;
+        if !amount.is_zero() {
+            rewards.push(Coin { denom, amount });
+        }
+    }
+
+    Ok(rewards)
+}
+
+// Settles the peer and takes the whole units of its pending rewards out to be paid
+pub fn take(storage: &mut dyn Storage, peer: &Addr, shares: Uint128) -> StdResult<Vec<Coin>> {
+    settle(storage, peer, shares)?;
+
+    let rewards = PEER_REWARDS
+        .prefix(peer)
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let mut paid = vec![];
+    for (denom, mut reward) in rewards {
+        let amount = whole(reward.pending)?;
+        if amount.is_zero() {
+            continue;
+        }
+
+        reward.pending = reward.pending.checked_sub(Decimal256::from_ratio(amount, 1u8))?;
+        PEER_REWARDS.save(storage, (peer, &denom), &reward)?;
+        paid.push(Coin { denom, amount });
+    }
+
+    Ok(paid)
+}
+
+// Drops all the bookkeeping of the peer. What it did not take is gathered in DUST, and goes back to
+// HELD for the next distribution as soon as it adds up to whole units.
+pub fn remove(storage: &mut dyn Storage, peer: &Addr) -> StdResult<()> {
+    let rewards = PEER_REWARDS
+        .prefix(peer)
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    for (denom, reward) in rewards {
+        PEER_REWARDS.remove(storage, (peer, &denom));
+        if reward.pending.is_zero() {
+            continue;
+        }
+
+        let dust = DUST.may_load(storage, &denom)?.unwrap_or_default().checked_add(reward.pending)?;
+        let amount = whole(dust)?;
+        DUST.save(storage, &denom, &dust.checked_sub(Decimal256::from_ratio(amount, 1u8))?)?;
+        if !amount.is_zero() {
+            HELD.update(storage, &denom, |held| -> StdResult<_> {
+                Ok(held.unwrap_or_default().checked_add(amount)?)
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+// Every denom with rewards has an entry in REWARD_INDEX, so it is settled and reported like the others
+fn known(storage: &mut dyn Storage, denom: &str) -> StdResult<()> {
+    if !REWARD_INDEX.has(storage, denom) {
+        REWARD_INDEX.save(storage, denom, &Decimal256::zero())?;
+    }
+    Ok(())
+}
+
+fn settled(mut reward: PeerReward, index: Decimal256, member_index: Decimal256, shares: Uint128) -> StdResult<PeerReward> {
+    let accrued = index
+        .checked_sub(reward.index)?
+        .checked_mul(Decimal256::from_ratio(shares, 1u8))?
+        .checked_add(member_index.checked_sub(reward.member_index)?)?;
+    reward.pending = reward.pending.checked_add(accrued)?;
+    reward.index = index;
+    reward.member_index = member_index;
+    Ok(reward)
+}
+
+fn whole(amount: Decimal256) -> StdResult<Uint128> {
+    Ok(Uint128::try_from(Uint256::one() * amount.floor())?)
+}
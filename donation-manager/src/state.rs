@@ -1,19 +1,292 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Addr, Coin, Decimal};
-use cw_storage_plus::{Item, Map};
+use cosmwasm_std::{Addr, Coin, Decimal, Decimal256, Isqrt, StdError, StdResult, Storage, Timestamp, Uint128};
+use cw_storage_plus::{Index, IndexedMap, IndexList, Item, Map, SnapshotMap, Strategy, UniqueIndex};
+use donation_peer::state::{Decay, DenomPolicy};
+
+// What the manager does with funds when no member has any donators yet
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ZeroWeightPolicy {
+    // Keep the funds until some member gets weight
+    #[default]
+    Hold,
+    // Split the funds equally between all members
+    SplitEqually,
+}
+
+// Scale of square root shares, so small weights still differ from each other
+const SQRT_PRECISION: u128 = 1_000_000;
+
+// How a donation is split between the members
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WeightStrategy {
+    // Proportionally to the donators of every peer
+    #[default]
+    Linear,
+    // Proportionally to the square root of the donators, dampens big peers
+    Sqrt,
+    // Linearly, but no peer gets more than `max_share` of a donation. The excess goes to the others,
+    // also the ones without weight, and only once every peer is capped is the cap exceeded
+    CappedLinear { max_share: Decimal },
+    // In equal parts, whatever the donators of the peers
+    Equal,
+}
+
+impl WeightStrategy {
+    // Shares of a peer with `weight` donators in REWARD_INDEX. Only the linear and square root
+    // strategies go through the index, the others have no shares at all.
+    pub fn shares(&self, weight: u64) -> Uint128 {
+        match self {
+            WeightStrategy::Linear => Uint128::from(weight),
+            WeightStrategy::Sqrt => Uint128::new(weight as u128 * SQRT_PRECISION).isqrt(),
+            WeightStrategy::CappedLinear { .. } | WeightStrategy::Equal => Uint128::zero(),
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Config {
     pub peer_code_id:u64,
     pub incremental_donation: Coin,
-    pub collective_ratio: Decimal
+    pub collective_ratio: Decimal,
+    pub other_denoms: DenomPolicy,
+    pub zero_weight: ZeroWeightPolicy,
+    // Instantiate peers with Instantiate2, so their addresses are known before they are created
+    #[serde(default)]
+    pub predictable_addresses: bool,
+    #[serde(default)]
+    pub weight_strategy: WeightStrategy,
+    // Part of every donation split equally between all members, the rest goes by `weight_strategy`
+    #[serde(default)]
+    pub floor_share: Decimal,
+    // Seconds donations are collected for before they are distributed together, with the weights the peers had
+    // when the collection started. With 0 every donation is distributed right away with the current weights
+    #[serde(default)]
+    pub epoch_length: u64,
+    // How the weight of donations to the peers fades over time
+    #[serde(default)]
+    pub decay: Decay,
+    // Part of the weight of a peer which comes from its unique donors, the rest comes from the increments
+    // donated to it. 0 weights by increments only, 1 by unique donors only. Donors are counted forever,
+    // so it has to stay 0 with a `decay`
+    #[serde(default)]
+    pub donor_share: Decimal,
 }
 
 pub const CONFIG: Item<Config> = Item::new("config");
 
-// peer address -> owner_address
-pub const MEMBERS: Map<Addr,Addr> = Map::new("members");
+// Config of the managers deployed before the versions were recorded with cw2, only read to migrate them
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LegacyConfig {
+    pub peer_code_id: u64,
+    pub incremental_donation: Coin,
+    pub collective_ratio: Decimal,
+}
+
+pub const LEGACY_CONFIG: Item<LegacyConfig> = Item::new("config");
+// peer address -> owner address, before members were indexed by owner
+pub const LEGACY_MEMBERS: Map<&Addr, Addr> = Map::new("members");
+// Owner of the peer instantiated by the last Join, before replies were told apart by id
+pub const LEGACY_PENDING_INSTANTIATION: Item<Addr> = Item::new("pending_instantiation");
+
+// None once the admin renounced
+pub const ADMIN: Item<Option<Addr>> = Item::new("admin");
+// Proposed admin, waiting to accept the role
+pub const PENDING_ADMIN: Item<Addr> = Item::new("pending_admin");
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Member {
+    pub owner: Addr,
+    pub joined_height: u64,
+    // Rewards are accrued with this weight, blended from the two numbers below
+    pub weight: u64,
+    // Increments donated to the peer, as last reported by it
+    #[serde(default)]
+    pub units: u64,
+    // Donors of the peer with at least one increment, as last reported by it
+    #[serde(default)]
+    pub unique_donors: u64,
+}
+
+impl Member {
+    // `donor_share` of the weight comes from the unique donors, the rest from the units. Only the units decay
+    pub fn blended_weight(&self, donor_share: Decimal) -> u64 {
+        let units = Decimal::from_ratio(self.units, 1u8) * (Decimal::one() - donor_share);
+        let donors = Decimal::from_ratio(self.unique_donors, 1u8) * donor_share;
+        (units + donors).to_uint_floor().u128() as u64
+    }
+}
+
+pub struct MemberIndexes<'a> {
+    // owner address -> peer address. Unique as every owner has at most one peer
+    pub owner: UniqueIndex<'a, Addr, Member, Addr>,
+}
+
+impl<'a> IndexList<Member> for MemberIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Member>> + '_> {
+        let v: Vec<&dyn Index<Member>> = vec![&self.owner];
+        Box::new(v.into_iter())
+    }
+}
+
+// peer address -> member (owner of the peer)
+pub fn members<'a>() -> IndexedMap<'a, Addr, Member, MemberIndexes<'a>> {
+    let indexes = MemberIndexes {
+        owner: UniqueIndex::new(|member| member.owner.clone(), "members__owner"),
+    };
+    IndexedMap::new("members", indexes)
+}
+
+// Peer owned by `owner`, if any
+pub fn peer_of(storage: &dyn Storage, owner: &Addr) -> StdResult<Option<Addr>> {
+    members()
+        .idx
+        .owner
+        .item(storage, owner.clone())?
+        .map(|(peer, _)| String::from_utf8(peer).map(Addr::unchecked).map_err(StdError::invalid_utf8))
+        .transpose()
+}
+
+pub const MEMBER_COUNT: Item<u64> = Item::new("member_count");
+
+// peer address -> weight of the member, snapshotted so epochs can use the weights at their start
+pub const WEIGHTS: SnapshotMap<&Addr, u64> = SnapshotMap::new(
+    "weights",
+    "weights__checkpoints",
+    "weights__changelog",
+    Strategy::EveryBlock,
+);
+
+// owner -> number of peers instantiated for it with Instantiate2, part of the salt of its next peer
+pub const PEER_SALT_NONCE: Map<&Addr, u32> = Map::new("peer_salt_nonce");
+
+// reply id -> owner of the peer being instantiated
+pub const PENDING_INSTANTIATION : Map<u64, Addr> = Map::new("pending_instantiation");
+pub const NEXT_REPLY_ID: Item<u64> = Item::new("next_reply_id");
+
+// Sum of the shares of all members, see `WeightStrategy::shares`
+pub const TOTAL_SHARES: Item<Uint128> = Item::new("total_shares");
+
+// Members being moved to their shares under a new weight strategy or donor share, in pages ordered by peer address
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Resettle {
+    // Strategy the members which were not moved yet hold their shares under
+    pub old_strategy: WeightStrategy,
+    // Last member moved
+    pub last: Option<Addr>,
+    // Shares of the members moved so far, TOTAL_SHARES once every member is moved
+    pub total_shares: Uint128,
+}
+
+// Only set while members are being moved
+pub const RESETTLE: Item<Resettle> = Item::new("resettle");
+
+// denom -> rewards per share accumulated since the manager was created
+pub const REWARD_INDEX: Map<&str, Decimal256> = Map::new("reward_index");
+// denom -> rewards every member got in equal parts since the manager was created
+pub const MEMBER_INDEX: Map<&str, Decimal256> = Map::new("member_index");
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct PeerReward {
+    // REWARD_INDEX at the last settlement of the peer
+    pub index: Decimal256,
+    // MEMBER_INDEX at the last settlement of the peer
+    #[serde(default)]
+    pub member_index: Decimal256,
+    // Accrued and not claimed yet. Fractions are kept until they add up to a whole unit
+    pub pending: Decimal256,
+}
+
+// (peer address, denom) -> peer rewards bookkeeping
+pub const PEER_REWARDS: Map<(&Addr, &str), PeerReward> = Map::new("peer_rewards");
+
+// denom -> funds donated while no member had any weight, waiting for the next donation
+pub const HELD: Map<&str, Uint128> = Map::new("held");
+// denom -> fractions of rewards left behind by members who left, below one unit
+pub const DUST: Map<&str, Decimal256> = Map::new("dust");
+
+// Quadratic funding round. Donations to the peers until `end` are matched from `pool` when the round is ended
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Round {
+    pub id: u64,
+    pub denom: String,
+    pub pool: Uint128,
+    pub end: Timestamp,
+}
+
+// Only set while a round is running
+pub const ROUND: Item<Round> = Item::new("round");
+
+// Round being ended. Its peers are gone through in pages twice, first to add up their quadratic weights,
+// then to pay out their matches
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RoundEnd {
+    pub round: Round,
+    // Last peer of the previous page
+    pub last: Option<Addr>,
+    // Set once the weights are added up
+    pub paying: bool,
+    pub total_weight: Uint128,
+    pub paid: Uint128,
+    pub peers: u64,
+}
+
+pub const ROUND_END: Item<RoundEnd> = Item::new("round_end");
+pub const ROUND_COUNT: Item<u64> = Item::new("round_count");
+
+// (round id, peer, donor) -> donated to the peer during the round, in the denom of the round
+pub const CONTRIBUTIONS: Map<(u64, &Addr, &Addr), Uint128> = Map::new("contributions");
+// (round id, peer) -> sum of the square roots of the contributions to the peer
+pub const SQRT_SUMS: Map<(u64, &Addr), Decimal256> = Map::new("sqrt_sums");
+// (round id, peer) -> matched from the pool once the round is ended
+pub const MATCHES: Map<(u64, &Addr), Uint128> = Map::new("matches");
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Epoch {
+    pub start: Timestamp,
+    pub end: Timestamp,
+    // Weights are taken as they were at the beginning of this block
+    pub start_height: u64,
+    // Donations collected during the epoch
+    pub funds: Vec<Coin>,
+    pub distributed: bool,
+    // How the funds are split, as configured when the epoch was opened
+    #[serde(default)]
+    pub weight_strategy: WeightStrategy,
+    #[serde(default)]
+    pub floor_share: Decimal,
+    #[serde(default)]
+    pub zero_weight: ZeroWeightPolicy,
+}
+
+// epoch id -> epoch, ids start at 1
+pub const EPOCHS: Map<u64, Epoch> = Map::new("epochs");
+// Last opened epoch, the one collecting donations until its end
+pub const CURRENT_EPOCH: Item<u64> = Item::new("current_epoch");
+// Oldest epoch which is not distributed yet
+pub const NEXT_DISTRIBUTION: Item<u64> = Item::new("next_distribution");
+// (epoch id, peer) -> paid out to the peer when the epoch was distributed
+pub const EPOCH_PAYOUTS: Map<(u64, &Addr), Vec<Coin>> = Map::new("epoch_payouts");
+
+// Epoch being distributed. The members are gone through in pages twice, first to add up the shares
+// they had when the epoch was opened, then to pay them out
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Distribution {
+    pub epoch: u64,
+    // Funds of the epoch together with what was held before
+    pub funds: Vec<Coin>,
+    // Last member of the previous page
+    pub last: Option<Addr>,
+    // Set once the shares are added up
+    pub paying: bool,
+    pub total_shares: Uint128,
+    // Members with a weight when the epoch was opened
+    pub eligible: u64,
+    pub paid: Vec<Coin>,
+    pub peers: u64,
+}
 
-pub const PENDING_INSTANTIATION : Item<Addr> = Item::new("pending_instantiation");
+pub const DISTRIBUTION: Item<Distribution> = Item::new("distribution");
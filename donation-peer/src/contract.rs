@@ -1,16 +1,26 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
-use cosmwasm_std::{Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult, to_binary};
-// use cw2::set_contract_version;
+use cosmwasm_std::{Addr, Binary, Coin, Decimal, Deps, DepsMut, Env, MessageInfo, Order, Response, StdResult, Storage, from_json, to_json_binary, WasmMsg};
+use cw_storage_plus::Bound;
+use cw20::{BalanceResponse, Cw20ExecuteMsg, Cw20QueryMsg, Cw20ReceiveMsg};
+use cw_utils::nonpayable;
+use cw2::set_contract_version;
+use semver::Version;
 
+use crate::decay;
 use crate::error::ContractError;
-use crate::msg::{DonatorsResponse, ExecuteMsg, InstantiateMsg, ManagerResp, OwnerResp, QueryMsg};
-use crate::state::{OWNER, State, STATE};
+use crate::helpers::{cw20_denom, cw20_token, migrating_from, send_coins};
+use crate::msg::{DonationInfo, DonatorsResponse, DonorInfo, DonorResponse, DonorsResponse, ExecuteMsg, InstantiateMsg, ManagerExecMsg, ManagerResp, MigrateMsg, OwnerResp, QueryMsg, ReceiveMsg, RecentDonationsResponse};
+use crate::state::{ANONYMOUS, Decay, DenomPolicy, Donation, DONATIONS, DonorRecord, DONORS, LEGACY_STATE, NEXT_DONATION_ID, OWNER, State, STATE};
 
-/*
 const CONTRACT_NAME: &str = "crates.io:donation-peer";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
- */
+
+// Pagination of the donors and donations lists
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
+
+const MAX_MEMO_LEN: usize = 280;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
@@ -20,15 +30,22 @@ pub fn instantiate(
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
 
+    validate_settings(&msg.incremental_donation, msg.collective_ratio, &msg.other_denoms, &msg.decay)?;
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
     let owner = deps.api.addr_validate(&msg.owner)?;
     OWNER.save(deps.storage,&owner)?;
 
     // The manager is the donation-manager contract address
     let state =  State {
         donators: 0,
+        unique_donors: 0,
         incremental_donation: msg.incremental_donation,
         collective_ratio: msg.collective_ratio,
+        other_denoms: msg.other_denoms,
         manager: info.sender,
+        detached: false,
+        decay: msg.decay,
     };
     STATE.save(deps.storage, &state)?;
 
@@ -36,42 +53,1093 @@ pub fn instantiate(
 
 }
 
+fn validate_settings(incremental_donation: &Coin, collective_ratio: Decimal, other_denoms: &DenomPolicy, decay: &Decay) -> Result<(), ContractError> {
+    if collective_ratio > Decimal::one() {
+        return Err(ContractError::InvalidRatio {});
+    }
+    if !decay.is_valid() {
+        return Err(ContractError::InvalidHalfLife {});
+    }
+
+    other_denoms.validate_increments(incremental_donation)?;
+
+    Ok(())
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(
-    _deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
-    _msg: ExecuteMsg,
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: ExecuteMsg,
+) -> Result<Response, ContractError> {
+    match msg {
+        ExecuteMsg::Donate { memo, anonymous } => {
+            if let Some(coin) = info.funds.iter().find(|coin| cw20_token(&coin.denom).is_some()) {
+                return Err(ContractError::InvalidDenom { denom: coin.denom.clone() });
+            }
+            donate(deps, env, info.sender, info.funds, memo, anonymous.unwrap_or(false))
+        }
+        ExecuteMsg::Receive(msg) => receive(deps, env, info, msg),
+        ExecuteMsg::Withdraw { amount, receiver } => withdraw(deps, env, info, amount, receiver),
+        ExecuteMsg::Detach {} => detach(deps, info),
+        ExecuteMsg::UpdateConfig { incremental_donation, collective_ratio, other_denoms, decay } => {
+            update_config(deps, env, info, incremental_donation, collective_ratio, other_denoms, decay)
+        }
+        ExecuteMsg::SyncWeight {} => sync_weight(deps, env, info),
+    }
+}
+
+// The message comes from the token contract, the donor is the one who sent the tokens
+fn receive(deps: DepsMut, env: Env, info: MessageInfo, msg: Cw20ReceiveMsg) -> Result<Response, ContractError> {
+    nonpayable(&info)?;
+    let donor = deps.api.addr_validate(&msg.sender)?;
+
+    match from_json(&msg.msg)? {
+        ReceiveMsg::Donate { memo, anonymous } => {
+            let funds = vec![Coin {
+                denom: cw20_denom(info.sender.as_str()),
+                amount: msg.amount,
+            }];
+            donate(deps, env, donor, funds, memo, anonymous.unwrap_or(false))
+        }
+    }
+}
+
+fn donate(
+    deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    funds: Vec<Coin>,
+    memo: Option<String>,
+    anonymous: bool,
+) -> Result<Response, ContractError> {
+    let funds: Vec<_> = funds.into_iter().filter(|coin| !coin.amount.is_zero()).collect();
+    if funds.is_empty() {
+        return Err(ContractError::NoFunds {});
+    }
+
+    let memo = memo.filter(|memo| !memo.is_empty());
+    if memo.as_ref().is_some_and(|memo| memo.chars().count() > MAX_MEMO_LEN) {
+        return Err(ContractError::MemoTooLong { max: MAX_MEMO_LEN });
+    }
+
+    let mut state = STATE.load(deps.storage)?;
+    let main_denom = &state.incremental_donation.denom;
+    if let Some(coin) = funds.iter().find(|coin| coin.denom != *main_denom && !state.other_denoms.accepts(&coin.denom)) {
+        return Err(ContractError::InvalidDenom { denom: coin.denom.clone() });
+    }
+
+    // Only the incremental_donation denom, and the allowlisted ones, add weight. The donator is counted
+    // once more every time their cumulative donation crosses another multiple of the increment of
+    // a denom, so dust donations add up over time and a donation of 3x the increment counts three times.
+    // Anonymous donations only add up with each other, in a record without an address.
+    let record = if anonymous {
+        ANONYMOUS.may_load(deps.storage)?
+    } else {
+        DONORS.may_load(deps.storage, &sender)?
+    };
+    let mut record = record.unwrap_or_else(|| DonorRecord {
+        donated: vec![],
+        first_height: env.block.height,
+        last_height: env.block.height,
+        weight: 0,
+    });
+
+    let increments: Vec<_> = std::iter::once(&state.incremental_donation)
+        .chain(state.other_denoms.increments())
+        .collect();
+    let before: Vec<_> = increments.iter().map(|increment| record.donated_in(&increment.denom)).collect();
+    for coin in &funds {
+        add_coin(&mut record.donated, coin)?;
+    }
+
+    let mut weight = 0;
+    for (increment, before) in increments.iter().zip(before) {
+        let after = record.donated_in(&increment.denom);
+        weight += (after.checked_div(increment.amount)? - before.checked_div(increment.amount)?).u128() as u64;
+    }
+    // Counted as a supporter with the first increment. Anonymous donors cannot be told apart, so they are not
+    if !anonymous && record.weight == 0 && weight > 0 {
+        state.unique_donors += 1;
+    }
+    record.weight += weight;
+    record.last_height = env.block.height;
+    if anonymous {
+        ANONYMOUS.save(deps.storage, &record)?;
+    } else {
+        DONORS.save(deps.storage, &sender, &record)?;
+    }
+
+    state.donators += weight;
+    STATE.save(deps.storage, &state)?;
+    if weight > 0 {
+        decay::add(deps.storage, &state.decay, &env.block, weight)?;
+    }
+
+    let id = NEXT_DONATION_ID.may_load(deps.storage)?.unwrap_or_default();
+    NEXT_DONATION_ID.save(deps.storage, &(id + 1))?;
+    let donation = Donation {
+        donor: (!anonymous).then(|| sender.clone()),
+        amount: funds.clone(),
+        memo: memo.clone(),
+        height: env.block.height,
+        time: env.block.time,
+    };
+    DONATIONS.save(deps.storage, id, &donation)?;
+
+    // The collective_ratio share of every coin goes to the manager, the rest stays on this contract.
+    // Coins whose share rounds down to zero are not forwarded at all, and a detached peer keeps everything.
+    let collective: Vec<_> = funds
+        .iter()
+        .filter(|_| !state.detached)
+        .map(|coin| Coin {
+            denom: coin.denom.clone(),
+            amount: coin.amount * state.collective_ratio,
+        })
+        .filter(|coin| !coin.amount.is_zero())
+        .collect();
+
+    let mut resp = Response::new()
+        .add_attribute("action", "donate")
+        .add_attribute("donation_id", id.to_string())
+        .add_attribute("weight", weight.to_string())
+        .add_attribute("donators", state.donators.to_string());
+    if !anonymous {
+        resp = resp.add_attribute("sender", sender.to_string());
+    }
+    if let Some(memo) = memo {
+        resp = resp.add_attribute("memo", memo);
+    }
+
+    // The manager caches the weight of every peer, so it is told about the new one first.
+    // This way the forwarded funds are already distributed with the new weight. A decaying weight
+    // changed since the last donation even if this one adds nothing, so it is pushed every time.
+    if (weight > 0 || state.decay != Decay::None) && !state.detached {
+        let weight = decay::reported(decay::weight(deps.storage, &state.decay, state.donators, &env.block)?)?;
+        resp = resp.add_message(update_weight_msg(&state, weight)?);
+    }
+
+    // Native coins go with a single Donate, CW20 tokens are sent one by one with the same hook
+    let mut native = vec![];
+    let mut sends = vec![];
+    for coin in collective {
+        match cw20_token(&coin.denom) {
+            Some(token) => sends.push(WasmMsg::Execute {
+                contract_addr: token.to_string(),
+                msg: to_json_binary(&Cw20ExecuteMsg::Send {
+                    contract: state.manager.to_string(),
+                    amount: coin.amount,
+                    // The hook message of the manager has the same shape as its Donate
+                    msg: to_json_binary(&ManagerExecMsg::Donate {})?,
+                })?,
+                funds: vec![],
+            }),
+            None => native.push(coin),
+        }
+    }
+    if !native.is_empty() {
+        let msg = WasmMsg::Execute {
+            contract_addr: state.manager.to_string(),
+            msg: to_json_binary(&ManagerExecMsg::Donate {})?,
+            funds: native,
+        };
+        resp = resp.add_message(msg);
+    }
+    resp = resp.add_messages(sends);
+
+    // Matching rounds count who donated, so anonymous donations are left out
+    if !anonymous && !state.detached {
+        let msg = WasmMsg::Execute {
+            contract_addr: state.manager.to_string(),
+            msg: to_json_binary(&ManagerExecMsg::RecordDonation { donor: sender.to_string(), funds })?,
+            funds: vec![],
+        };
+        resp = resp.add_message(msg);
+    }
+
+    Ok(resp)
+}
+
+fn update_weight_msg(state: &State, weight: u64) -> StdResult<WasmMsg> {
+    Ok(WasmMsg::Execute {
+        contract_addr: state.manager.to_string(),
+        msg: to_json_binary(&ManagerExecMsg::UpdateWeight { weight, unique_donors: state.unique_donors })?,
+        funds: vec![],
+    })
+}
+
+// Donations made before keep the weight they got. The new incremental_donation is only used to count
+// thresholds crossed from now on. A new decay starts from the current weight of the peer.
+fn update_config(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    incremental_donation: Coin,
+    collective_ratio: Decimal,
+    other_denoms: DenomPolicy,
+    decay: Decay,
 ) -> Result<Response, ContractError> {
-    unimplemented!()
+    let mut state = STATE.load(deps.storage)?;
+    if info.sender != state.manager {
+        return Err(ContractError::Unauthorized {});
+    }
+    validate_settings(&incremental_donation, collective_ratio, &other_denoms, &decay)?;
+
+    let mut resp = Response::new().add_attribute("action", "update_config");
+    if decay != state.decay {
+        let weight = decay::weight(deps.storage, &state.decay, state.donators, &env.block)?;
+        decay::reset(deps.storage, &decay, &env.block, weight)?;
+        // Without decay the weight is `donators` again
+        let weight = decay::reported(decay::weight(deps.storage, &decay, state.donators, &env.block)?)?;
+        if !state.detached {
+            resp = resp.add_message(update_weight_msg(&state, weight)?);
+        }
+    }
+
+    state.incremental_donation = incremental_donation;
+    state.collective_ratio = collective_ratio;
+    state.other_denoms = other_denoms;
+    state.decay = decay;
+    STATE.save(deps.storage, &state)?;
+
+    Ok(resp)
+}
+
+fn sync_weight(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
+    nonpayable(&info)?;
+    let state = STATE.load(deps.storage)?;
+    if state.detached {
+        return Err(ContractError::Detached {});
+    }
+
+    let weight = decay::reported(decay::weight(deps.storage, &state.decay, state.donators, &env.block)?)?;
+    let resp = Response::new()
+        .add_message(update_weight_msg(&state, weight)?)
+        .add_attribute("action", "sync_weight")
+        .add_attribute("weight", weight.to_string());
+    Ok(resp)
+}
+
+fn add_coin(coins: &mut Vec<Coin>, coin: &Coin) -> Result<(), ContractError> {
+    match coins.iter_mut().find(|c| c.denom == coin.denom) {
+        Some(c) => c.amount = c.amount.checked_add(coin.amount)?,
+        None => coins.push(coin.clone()),
+    }
+    Ok(())
+}
+
+fn detach(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    nonpayable(&info)?;
+    let mut state = STATE.load(deps.storage)?;
+    if info.sender != state.manager {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    state.detached = true;
+    STATE.save(deps.storage, &state)?;
+
+    Ok(Response::new().add_attribute("action", "detach"))
+}
+
+fn withdraw(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    amount: Option<Vec<Coin>>,
+    receiver: Option<String>,
+) -> Result<Response, ContractError> {
+    nonpayable(&info)?;
+    let owner = OWNER.load(deps.storage)?;
+    if info.sender != owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    // Funds go to the owner unless another address (e.g. a cold wallet) is given
+    let receiver = match receiver {
+        Some(receiver) => deps.api.addr_validate(&receiver)?,
+        None => owner,
+    };
+
+    // Without an explicit amount the whole balance of the contract is withdrawn,
+    // together with the balances of the CW20 tokens it accepts
+    let amount = match amount {
+        Some(amount) => amount,
+        None => {
+            let mut amount = deps.querier.query_all_balances(&env.contract.address)?;
+            let state = STATE.load(deps.storage)?;
+            let tokens = std::iter::once(&state.incremental_donation)
+                .chain(state.other_denoms.increments())
+                .filter_map(|increment| cw20_token(&increment.denom));
+            for token in tokens {
+                let balance: BalanceResponse = deps.querier.query_wasm_smart(
+                    token,
+                    &Cw20QueryMsg::Balance { address: env.contract.address.to_string() },
+                )?;
+                amount.push(Coin { denom: cw20_denom(token), amount: balance.balance });
+            }
+            amount
+        }
+    };
+    let amount: Vec<_> = amount.into_iter().filter(|coin| !coin.amount.is_zero()).collect();
+
+    if amount.is_empty() {
+        return Err(ContractError::NothingToWithdraw {});
+    }
+
+    let mut resp = Response::new()
+        .add_messages(send_coins(receiver.as_str(), amount)?)
+        .add_attribute("action", "withdraw")
+        .add_attribute("receiver", receiver.to_string());
+
+    // The owner takes the chance to bring the decayed weight on the manager up to date
+    let state = STATE.load(deps.storage)?;
+    if state.decay != Decay::None && !state.detached {
+        let weight = decay::reported(decay::weight(deps.storage, &state.decay, state.donators, &env.block)?)?;
+        resp = resp.add_message(update_weight_msg(&state, weight)?);
+    }
+
+    Ok(resp)
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
-        QueryMsg::Donators {} => query_donators(deps),
+        QueryMsg::Donators {} => query_donators(deps, env),
         QueryMsg::Owner {} => query_owner(deps),
         QueryMsg::Manager {} => query_manager(deps),
+        QueryMsg::Donor { addr } => to_json_binary(&query_donor(deps, addr)?),
+        QueryMsg::Donors { start_after, limit } => to_json_binary(&query_donors(deps, start_after, limit)?),
+        QueryMsg::RecentDonations { start_after, limit } => to_json_binary(&query_recent_donations(deps, start_after, limit)?),
     }
 
 }
 
-fn query_donators (deps: Deps ) -> StdResult<Binary> {
+fn query_donators (deps: Deps, env: Env) -> StdResult<Binary> {
     let state = STATE.load(deps.storage)?;
-    Ok(to_binary(&DonatorsResponse{ donators: state.donators })?)
+    let weight = decay::reported(decay::weight(deps.storage, &state.decay, state.donators, &env.block)?)?;
+    to_json_binary(&DonatorsResponse{ donators: state.donators, unique_donors: state.unique_donors, weight })
 }
 
 fn query_owner (deps: Deps) -> StdResult<Binary> {
     let owner = OWNER.load(deps.storage)?;
-    let resp = to_binary(&OwnerResp{owner})?;
+    let resp = to_json_binary(&OwnerResp{owner})?;
     Ok(resp)
 }
 
 fn query_manager (deps: Deps) -> StdResult<Binary> {
     let state = STATE.load(deps.storage)?;
-    let resp = to_binary(&ManagerResp{ manager: state.manager })?;
+    let resp = to_json_binary(&ManagerResp{ manager: state.manager, detached: state.detached })?;
+    Ok(resp)
+}
+
+fn query_donor(deps: Deps, addr: String) -> StdResult<DonorResponse> {
+    let addr = deps.api.addr_validate(&addr)?;
+    let donor = DONORS
+        .may_load(deps.storage, &addr)?
+        .map(|record| DonorInfo { addr, record });
+    Ok(DonorResponse { donor })
+}
+
+fn query_donors(deps: Deps, start_after: Option<String>, limit: Option<u32>) -> StdResult<DonorsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_after = start_after.map(|addr| deps.api.addr_validate(&addr)).transpose()?;
+    let start = start_after.as_ref().map(Bound::exclusive);
+
+    let donors = DONORS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(addr, record)| DonorInfo { addr, record }))
+        .collect::<StdResult<_>>()?;
+
+    Ok(DonorsResponse { donors })
+}
+
+fn query_recent_donations(deps: Deps, start_after: Option<u64>, limit: Option<u32>) -> StdResult<RecentDonationsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let end = start_after.map(Bound::exclusive);
+
+    let donations = DONATIONS
+        .range(deps.storage, None, end, Order::Descending)
+        .take(limit)
+        .map(|donation| donation.map(|(id, donation)| DonationInfo { id, donation }))
+        .collect::<StdResult<_>>()?;
+
+    Ok(RecentDonationsResponse { donations })
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    let stored = migrating_from(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    let from = match &stored {
+        Some(version) => version.clone(),
+        None => migrate_legacy(deps.storage)?,
+    };
+
+    migrate_state(deps.storage, &from)?;
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    let resp = Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("from_version", stored.map_or("legacy".to_string(), |version| version.to_string()))
+        .add_attribute("to_version", CONTRACT_VERSION);
+
     Ok(resp)
 }
 
+// Brings a peer deployed before the versions were recorded to the layout of 0.1.0. Such peers could not
+// take donations yet, so there are no donors to carry over
+fn migrate_legacy(storage: &mut dyn Storage) -> Result<Version, ContractError> {
+    let legacy = LEGACY_STATE.load(storage)?;
+    let state = State {
+        donators: legacy.donators,
+        unique_donors: 0,
+        incremental_donation: legacy.incremental_donation,
+        collective_ratio: legacy.collective_ratio,
+        // The manager took every denom back then
+        other_denoms: DenomPolicy::PassThrough,
+        manager: legacy.manager,
+        detached: false,
+        decay: Decay::None,
+    };
+    STATE.save(storage, &state)?;
+    Ok(Version::new(0, 1, 0))
+}
+
+// Brings the storage layout of `from` up to date, see `migrating_from`
+fn migrate_state(storage: &mut dyn Storage, from: &Version) -> Result<(), ContractError> {
+    // 0.2.0: the weight may decay and supporters are counted apart from the increments. Older peers keep
+    // counting every donation, the decay storage (DECAYED, ALIVE, EXPIRING) starts empty until a decay is set.
+    // 0.3.0 only pushes the decayed weight more often, nothing stored changes.
+    if *from < Version::new(0, 2, 0) {
+        let mut unique_donors = 0;
+        for record in DONORS.range(storage, None, None, Order::Ascending) {
+            if record?.1.weight > 0 {
+                unique_donors += 1;
+            }
+        }
+        STATE.update(storage, |mut state| -> StdResult<_> {
+            state.unique_donors = unique_donors;
+            state.decay = Decay::None;
+            Ok(state)
+        })?;
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use cosmwasm_std::{Addr, BankMsg, coin, coins, CosmosMsg, from_json, OwnedDeps, SubMsg};
+    use cosmwasm_std::testing::{mock_dependencies_with_balance, mock_env, mock_info, MockApi, MockQuerier, MockStorage};
+    use cosmwasm_std::Uint128;
+    use cw_utils::{Duration, PaymentError};
+    use cw2::get_contract_version;
+
+    use super::*;
+    use crate::state::LegacyState;
+
+    fn setup(balance: &[Coin]) -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+        setup_with_policy(balance, DenomPolicy::PassThrough)
+    }
+
+    fn setup_with_policy(balance: &[Coin], other_denoms: DenomPolicy) -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+        let mut deps = mock_dependencies_with_balance(balance);
+        instantiate(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("manager", &[]),
+            InstantiateMsg {
+                owner: "owner".to_string(),
+                incremental_donation: coin(100, "utgd"),
+                collective_ratio: Decimal::percent(60),
+                other_denoms,
+                decay: Decay::None,
+            },
+        )
+        .unwrap();
+        deps
+    }
+
+    fn donators(deps: Deps) -> u64 {
+        let resp: DonatorsResponse = from_json(query(deps, mock_env(), QueryMsg::Donators {}).unwrap()).unwrap();
+        resp.donators
+    }
+
+    // Increments donated so far and the current weight of the peer
+    fn weights(deps: Deps, env: &Env) -> (u64, u64) {
+        let resp: DonatorsResponse = from_json(query(deps, env.clone(), QueryMsg::Donators {}).unwrap()).unwrap();
+        (resp.donators, resp.weight)
+    }
+
+    fn update_decay(decay: Decay) -> ExecuteMsg {
+        ExecuteMsg::UpdateConfig {
+            incremental_donation: coin(100, "utgd"),
+            collective_ratio: Decimal::percent(60),
+            other_denoms: DenomPolicy::PassThrough,
+            decay,
+        }
+    }
+
+    fn update_weight(weight: u64, unique_donors: u64) -> SubMsg {
+        SubMsg::new(WasmMsg::Execute {
+            contract_addr: "manager".to_string(),
+            msg: to_json_binary(&ManagerExecMsg::UpdateWeight { weight, unique_donors }).unwrap(),
+            funds: vec![],
+        })
+    }
+
+    fn withdraw_msg(amount: Option<Vec<Coin>>, receiver: Option<String>) -> ExecuteMsg {
+        ExecuteMsg::Withdraw { amount, receiver }
+    }
+
+    #[test]
+    fn withdraw_whole_balance() {
+        let mut deps = setup(&[coin(100, "utgd"), coin(5, "uatom")]);
+
+        let resp = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), withdraw_msg(None, None)).unwrap();
+        assert_eq!(
+            resp.messages,
+            vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "owner".to_string(),
+                amount: vec![coin(100, "utgd"), coin(5, "uatom")],
+            }))]
+        );
+    }
+
+    #[test]
+    fn withdraw_partial_to_receiver() {
+        let mut deps = setup(&coins(100, "utgd"));
+
+        let resp = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("owner", &[]),
+            withdraw_msg(Some(coins(30, "utgd")), Some("cold_wallet".to_string())),
+        )
+        .unwrap();
+        assert_eq!(
+            resp.messages,
+            vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "cold_wallet".to_string(),
+                amount: coins(30, "utgd"),
+            }))]
+        );
+    }
+
+    #[test]
+    fn withdraw_unauthorized() {
+        let mut deps = setup(&coins(100, "utgd"));
+
+        let err = execute(deps.as_mut(), mock_env(), mock_info("stranger", &[]), withdraw_msg(None, None)).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+
+        // Owner query still reports the original owner
+        let owner: OwnerResp = from_json(query(deps.as_ref(), mock_env(), QueryMsg::Owner {}).unwrap()).unwrap();
+        assert_eq!(owner.owner, "owner");
+    }
+
+    #[test]
+    fn withdraw_empty_balance() {
+        let mut deps = setup(&[]);
+
+        let err = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), withdraw_msg(None, None)).unwrap_err();
+        assert_eq!(err, ContractError::NothingToWithdraw {});
+
+        // Withdrawals do not take funds
+        let err = execute(deps.as_mut(), mock_env(), mock_info("owner", &coins(5, "utgd")), withdraw_msg(None, None)).unwrap_err();
+        assert_eq!(err, ContractError::Payment(PaymentError::NonPayable {}));
+    }
+
+    #[test]
+    fn donation_weight_follows_increments() {
+        let mut deps = setup(&[]);
+
+        // 3x the increment counts three times
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &coins(300, "utgd")), ExecuteMsg::Donate { memo: None, anonymous: None }).unwrap();
+        assert_eq!(donators(deps.as_ref()), 3);
+
+        // Dust does not count until the cumulative donation crosses the next multiple
+        execute(deps.as_mut(), mock_env(), mock_info("bob", &coins(60, "utgd")), ExecuteMsg::Donate { memo: None, anonymous: None }).unwrap();
+        assert_eq!(donators(deps.as_ref()), 3);
+        execute(deps.as_mut(), mock_env(), mock_info("bob", &coins(60, "utgd")), ExecuteMsg::Donate { memo: None, anonymous: None }).unwrap();
+        assert_eq!(donators(deps.as_ref()), 4);
+
+        // Another donator's dust is tracked separately
+        execute(deps.as_mut(), mock_env(), mock_info("carol", &coins(99, "utgd")), ExecuteMsg::Donate { memo: None, anonymous: None }).unwrap();
+        assert_eq!(donators(deps.as_ref()), 4);
+    }
+
+    #[test]
+    fn unique_donors_and_units() {
+        let mut deps = setup(&[]);
+        let env = mock_env();
+        let counts = |deps: Deps| -> (u64, u64) {
+            let resp: DonatorsResponse = from_json(query(deps, mock_env(), QueryMsg::Donators {}).unwrap()).unwrap();
+            (resp.unique_donors, resp.donators)
+        };
+
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &coins(300, "utgd")), ExecuteMsg::Donate { memo: None, anonymous: None }).unwrap();
+        // Dust does not make a supporter until it adds up to an increment
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &coins(60, "utgd")), ExecuteMsg::Donate { memo: None, anonymous: None }).unwrap();
+        assert_eq!(counts(deps.as_ref()), (1, 3));
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &coins(60, "utgd")), ExecuteMsg::Donate { memo: None, anonymous: None }).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &coins(100, "utgd")), ExecuteMsg::Donate { memo: None, anonymous: None }).unwrap();
+        assert_eq!(counts(deps.as_ref()), (2, 5));
+
+        // Supporters of older peers are counted on migration
+        STATE.update(deps.as_mut().storage, |mut state| -> StdResult<_> {
+            state.unique_donors = 0;
+            Ok(state)
+        })
+        .unwrap();
+        set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "0.1.0").unwrap();
+        migrate(deps.as_mut(), env, MigrateMsg {}).unwrap();
+        assert_eq!(counts(deps.as_ref()), (2, 5));
+    }
+
+    #[test]
+    fn other_denoms_pass_through() {
+        let mut deps = setup(&[]);
+
+        let resp = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[coin(1000, "uatom")]),
+            ExecuteMsg::Donate { memo: None, anonymous: None },
+        )
+        .unwrap();
+        assert_eq!(donators(deps.as_ref()), 0);
+
+        // The collective share is still forwarded to the manager
+        assert_eq!(
+            resp.messages,
+            vec![
+                SubMsg::new(WasmMsg::Execute {
+                    contract_addr: "manager".to_string(),
+                    msg: to_json_binary(&ManagerExecMsg::Donate {}).unwrap(),
+                    funds: coins(600, "uatom"),
+                }),
+                SubMsg::new(WasmMsg::Execute {
+                    contract_addr: "manager".to_string(),
+                    msg: to_json_binary(&ManagerExecMsg::RecordDonation {
+                        donor: "alice".to_string(),
+                        funds: coins(1000, "uatom"),
+                    })
+                    .unwrap(),
+                    funds: vec![],
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn other_denoms_rejected() {
+        let mut deps = setup_with_policy(&[], DenomPolicy::Reject);
+
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[coin(100, "utgd"), coin(1000, "uatom")]),
+            ExecuteMsg::Donate { memo: None, anonymous: None },
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::InvalidDenom { denom: "uatom".to_string() });
+        assert_eq!(donators(deps.as_ref()), 0);
+    }
+
+    #[test]
+    fn allowlisted_denoms_add_weight() {
+        let mut deps = setup_with_policy(&[], DenomPolicy::Allowlist(vec![coin(1000, "uatom")]));
+
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[coin(100, "utgd"), coin(5, "ujuno")]),
+            ExecuteMsg::Donate { memo: None, anonymous: None },
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::InvalidDenom { denom: "ujuno".to_string() });
+
+        // Every denom crosses its own increments: 1 for utgd, 2 for uatom
+        let resp = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[coin(150, "utgd"), coin(2500, "uatom")]),
+            ExecuteMsg::Donate { memo: None, anonymous: None },
+        )
+        .unwrap();
+        assert!(resp.attributes.iter().any(|attr| attr.key == "weight" && attr.value == "3"));
+
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &coins(500, "uatom")), ExecuteMsg::Donate { memo: None, anonymous: None }).unwrap();
+        assert_eq!(donators(deps.as_ref()), 4);
+    }
+
+    #[test]
+    fn donor_ledger() {
+        let mut deps = setup(&[]);
+
+        let mut env = mock_env();
+        env.block.height = 10;
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &coins(150, "utgd")), ExecuteMsg::Donate { memo: None, anonymous: None }).unwrap();
+        env.block.height = 20;
+        execute(deps.as_mut(), env, mock_info("alice", &[coin(50, "utgd"), coin(7, "uatom")]), ExecuteMsg::Donate { memo: None, anonymous: None }).unwrap();
+
+        let resp: DonorResponse = from_json(query(deps.as_ref(), mock_env(), QueryMsg::Donor { addr: "alice".to_string() }).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            resp.donor,
+            Some(DonorInfo {
+                addr: Addr::unchecked("alice"),
+                record: DonorRecord {
+                    donated: vec![coin(200, "utgd"), coin(7, "uatom")],
+                    first_height: 10,
+                    last_height: 20,
+                    weight: 2,
+                },
+            })
+        );
+
+        let resp: DonorResponse = from_json(query(deps.as_ref(), mock_env(), QueryMsg::Donor { addr: "bob".to_string() }).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(resp.donor, None);
+    }
+
+    #[test]
+    fn donors_pagination() {
+        let mut deps = setup(&[]);
+        for donor in ["carol", "alice", "bob"] {
+            execute(deps.as_mut(), mock_env(), mock_info(donor, &coins(100, "utgd")), ExecuteMsg::Donate { memo: None, anonymous: None }).unwrap();
+        }
+
+        let donors = |start_after: Option<&str>, limit| -> Vec<String> {
+            let resp: DonorsResponse = from_json(query(
+                    deps.as_ref(),
+                    mock_env(),
+                    QueryMsg::Donors { start_after: start_after.map(str::to_string), limit },
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            resp.donors.into_iter().map(|donor| donor.addr.to_string()).collect()
+        };
+
+        assert_eq!(donors(None, None), vec!["alice", "bob", "carol"]);
+        assert_eq!(donors(None, Some(2)), vec!["alice", "bob"]);
+        assert_eq!(donors(Some("bob"), Some(2)), vec!["carol"]);
+    }
+
+    #[test]
+    fn cw20_donations() {
+        let mut deps = setup_with_policy(&[], DenomPolicy::Allowlist(vec![coin(10, "cw20:token")]));
+        let receive = |sender: &str, amount| ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: sender.to_string(),
+            amount: Uint128::new(amount),
+            msg: to_json_binary(&ReceiveMsg::Donate { memo: None, anonymous: None }).unwrap(),
+        });
+
+        // The donor is the sender of the tokens, not the token contract
+        let resp = execute(deps.as_mut(), mock_env(), mock_info("token", &[]), receive("alice", 25)).unwrap();
+        assert_eq!(
+            resp.messages,
+            vec![
+                SubMsg::new(WasmMsg::Execute {
+                    contract_addr: "manager".to_string(),
+                    msg: to_json_binary(&ManagerExecMsg::UpdateWeight { weight: 2, unique_donors: 1 }).unwrap(),
+                    funds: vec![],
+                }),
+                SubMsg::new(WasmMsg::Execute {
+                    contract_addr: "token".to_string(),
+                    msg: to_json_binary(&Cw20ExecuteMsg::Send {
+                        contract: "manager".to_string(),
+                        amount: Uint128::new(15),
+                        msg: to_json_binary(&ManagerExecMsg::Donate {}).unwrap(),
+                    })
+                    .unwrap(),
+                    funds: vec![],
+                }),
+                SubMsg::new(WasmMsg::Execute {
+                    contract_addr: "manager".to_string(),
+                    msg: to_json_binary(&ManagerExecMsg::RecordDonation {
+                        donor: "alice".to_string(),
+                        funds: coins(25, "cw20:token"),
+                    })
+                    .unwrap(),
+                    funds: vec![],
+                }),
+            ]
+        );
+        let resp: DonorResponse = from_json(query(deps.as_ref(), mock_env(), QueryMsg::Donor { addr: "alice".to_string() }).unwrap()).unwrap();
+        assert_eq!(resp.donor.unwrap().record.donated, coins(25, "cw20:token"));
+
+        // Tokens which are not listed are rejected, and native coins cannot pose as tokens
+        let err = execute(deps.as_mut(), mock_env(), mock_info("other", &[]), receive("alice", 25)).unwrap_err();
+        assert_eq!(err, ContractError::InvalidDenom { denom: "cw20:other".to_string() });
+        let err = execute(deps.as_mut(), mock_env(), mock_info("alice", &coins(25, "cw20:token")), ExecuteMsg::Donate { memo: None, anonymous: None }).unwrap_err();
+        assert_eq!(err, ContractError::InvalidDenom { denom: "cw20:token".to_string() });
+    }
+
+    #[test]
+    fn cw20_not_passed_through() {
+        let mut deps = setup(&[]);
+        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: "alice".to_string(),
+            amount: Uint128::new(25),
+            msg: to_json_binary(&ReceiveMsg::Donate { memo: None, anonymous: None }).unwrap(),
+        });
+
+        let err = execute(deps.as_mut(), mock_env(), mock_info("token", &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::InvalidDenom { denom: "cw20:token".to_string() });
+    }
+
+    #[test]
+    fn memos_and_recent_donations() {
+        let mut deps = setup(&[]);
+        let donate = |memo: Option<&str>, anonymous| ExecuteMsg::Donate {
+            memo: memo.map(str::to_string),
+            anonymous: Some(anonymous),
+        };
+
+        let err = execute(deps.as_mut(), mock_env(), mock_info("alice", &coins(100, "utgd")), donate(Some(&"a".repeat(281)), false))
+            .unwrap_err();
+        assert_eq!(err, ContractError::MemoTooLong { max: 280 });
+
+        let resp = execute(deps.as_mut(), mock_env(), mock_info("alice", &coins(100, "utgd")), donate(Some("thanks!"), false))
+            .unwrap();
+        assert!(resp.attributes.iter().any(|attr| attr.key == "memo" && attr.value == "thanks!"));
+        assert!(resp.attributes.iter().any(|attr| attr.key == "sender" && attr.value == "alice"));
+
+        // Anonymous donations still count, but the donor is neither emitted nor listed
+        let resp = execute(deps.as_mut(), mock_env(), mock_info("bob", &coins(100, "utgd")), donate(Some("keep going"), true))
+            .unwrap();
+        assert!(resp.attributes.iter().all(|attr| attr.key != "sender"));
+        execute(deps.as_mut(), mock_env(), mock_info("carol", &coins(5, "utgd")), donate(None, false)).unwrap();
+        assert_eq!(donators(deps.as_ref()), 2);
+
+        let donors: DonorsResponse = from_json(query(deps.as_ref(), mock_env(), QueryMsg::Donors { start_after: None, limit: None }).unwrap()).unwrap();
+        let donors: Vec<_> = donors.donors.into_iter().map(|info| info.addr.to_string()).collect();
+        assert_eq!(donors, vec!["alice", "carol"]);
+        let bob: DonorResponse = from_json(query(deps.as_ref(), mock_env(), QueryMsg::Donor { addr: "bob".to_string() }).unwrap()).unwrap();
+        assert_eq!(bob.donor, None);
+
+        let recent = |deps: Deps, start_after, limit| -> Vec<(u64, Option<String>, Option<String>)> {
+            let resp: RecentDonationsResponse = from_json(query(deps, mock_env(), QueryMsg::RecentDonations { start_after, limit }).unwrap()).unwrap();
+            resp.donations
+                .into_iter()
+                .map(|info| (info.id, info.donation.donor.map(|donor| donor.to_string()), info.donation.memo))
+                .collect()
+        };
+        assert_eq!(
+            recent(deps.as_ref(), None, Some(2)),
+            vec![(2, Some("carol".to_string()), None), (1, None, Some("keep going".to_string()))]
+        );
+        assert_eq!(recent(deps.as_ref(), Some(1), None), vec![(0, Some("alice".to_string()), Some("thanks!".to_string()))]);
+    }
+
+    #[test]
+    fn detached_peer_keeps_donations() {
+        let mut deps = setup(&[]);
+
+        let err = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), ExecuteMsg::Detach {}).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+
+        execute(deps.as_mut(), mock_env(), mock_info("manager", &[]), ExecuteMsg::Detach {}).unwrap();
+
+        let resp = execute(deps.as_mut(), mock_env(), mock_info("alice", &coins(100, "utgd")), ExecuteMsg::Donate { memo: None, anonymous: None }).unwrap();
+        assert!(resp.messages.is_empty());
+        assert_eq!(donators(deps.as_ref()), 1);
+    }
+
+    #[test]
+    fn weight_change_is_pushed_to_manager() {
+        let mut deps = setup(&[]);
+
+        let resp = execute(deps.as_mut(), mock_env(), mock_info("alice", &coins(200, "utgd")), ExecuteMsg::Donate { memo: None, anonymous: None }).unwrap();
+        assert_eq!(
+            resp.messages,
+            vec![
+                SubMsg::new(WasmMsg::Execute {
+                    contract_addr: "manager".to_string(),
+                    msg: to_json_binary(&ManagerExecMsg::UpdateWeight { weight: 2, unique_donors: 1 }).unwrap(),
+                    funds: vec![],
+                }),
+                SubMsg::new(WasmMsg::Execute {
+                    contract_addr: "manager".to_string(),
+                    msg: to_json_binary(&ManagerExecMsg::Donate {}).unwrap(),
+                    funds: coins(120, "utgd"),
+                }),
+                SubMsg::new(WasmMsg::Execute {
+                    contract_addr: "manager".to_string(),
+                    msg: to_json_binary(&ManagerExecMsg::RecordDonation {
+                        donor: "alice".to_string(),
+                        funds: coins(200, "utgd"),
+                    })
+                    .unwrap(),
+                    funds: vec![],
+                }),
+            ]
+        );
+
+        // Dust does not change the weight, so the manager only gets the funds and the record
+        let resp = execute(deps.as_mut(), mock_env(), mock_info("alice", &coins(10, "utgd")), ExecuteMsg::Donate { memo: None, anonymous: None }).unwrap();
+        assert_eq!(resp.messages.len(), 2);
+
+        // Anonymous donations are not recorded
+        let resp = execute(deps.as_mut(), mock_env(), mock_info("bob", &coins(10, "utgd")), ExecuteMsg::Donate { memo: None, anonymous: Some(true) }).unwrap();
+        assert_eq!(resp.messages.len(), 1);
+    }
+
+    #[test]
+    fn exponential_decay() {
+        let mut deps = setup(&[]);
+        let mut env = mock_env();
+
+        let err = execute(deps.as_mut(), env.clone(), mock_info("manager", &[]), update_decay(Decay::Exponential { half_life: Duration::Time(0) }))
+            .unwrap_err();
+        assert_eq!(err, ContractError::InvalidHalfLife {});
+        // Expiry points of linear decay would not fit in a u64
+        let err = execute(deps.as_mut(), env.clone(), mock_info("manager", &[]), update_decay(Decay::Linear { half_life: Duration::Height(u64::MAX) }))
+            .unwrap_err();
+        assert_eq!(err, ContractError::InvalidHalfLife {});
+        execute(deps.as_mut(), env.clone(), mock_info("manager", &[]), update_decay(Decay::Exponential { half_life: Duration::Time(100) }))
+            .unwrap();
+
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &coins(400, "utgd")), ExecuteMsg::Donate { memo: None, anonymous: None }).unwrap();
+        assert_eq!(weights(deps.as_ref(), &env), (4, 4));
+
+        env.block.time = env.block.time.plus_seconds(100);
+        assert_eq!(weights(deps.as_ref(), &env), (4, 2));
+
+        // 4 * 0.5^1.5 is 1.41, the fraction is not reported
+        env.block.time = env.block.time.plus_seconds(50);
+        assert_eq!(weights(deps.as_ref(), &env), (4, 1));
+        let resp = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), ExecuteMsg::SyncWeight {}).unwrap();
+        assert_eq!(resp.messages, vec![update_weight(1, 1)]);
+
+        // A new donation adds to the decayed weight
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &coins(200, "utgd")), ExecuteMsg::Donate { memo: None, anonymous: None }).unwrap();
+        assert_eq!(weights(deps.as_ref(), &env), (6, 3));
+
+        // The manager gets the decayed weight with every donation, dust included, and with every withdrawal
+        env.block.time = env.block.time.plus_seconds(100);
+        let resp = execute(deps.as_mut(), env.clone(), mock_info("carol", &coins(50, "utgd")), ExecuteMsg::Donate { memo: None, anonymous: None }).unwrap();
+        assert_eq!(resp.messages[0], update_weight(1, 2));
+        let resp = execute(deps.as_mut(), env.clone(), mock_info("owner", &[]), withdraw_msg(Some(coins(10, "utgd")), None)).unwrap();
+        assert!(resp.messages.contains(&update_weight(1, 2)));
+    }
+
+    #[test]
+    fn linear_decay() {
+        let mut deps = setup(&[]);
+        let mut env = mock_env();
+        execute(deps.as_mut(), env.clone(), mock_info("manager", &[]), update_decay(Decay::Linear { half_life: Duration::Height(10) }))
+            .unwrap();
+
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &coins(400, "utgd")), ExecuteMsg::Donate { memo: None, anonymous: None }).unwrap();
+        env.block.height += 10;
+        assert_eq!(weights(deps.as_ref(), &env), (4, 2));
+
+        // The manager gets the decayed weight along with a donation
+        let resp = execute(deps.as_mut(), env.clone(), mock_info("bob", &coins(200, "utgd")), ExecuteMsg::Donate { memo: None, anonymous: None }).unwrap();
+        assert_eq!(resp.messages[0], update_weight(4, 2));
+
+        // Alice's donation stopped counting, half of Bob's is left
+        env.block.height += 10;
+        assert_eq!(weights(deps.as_ref(), &env), (6, 1));
+        env.block.height += 10;
+        assert_eq!(weights(deps.as_ref(), &env), (6, 0));
+
+        // Without decay every increment counts again
+        let resp = execute(deps.as_mut(), env.clone(), mock_info("manager", &[]), update_decay(Decay::None)).unwrap();
+        assert_eq!(resp.messages, vec![update_weight(6, 2)]);
+        assert_eq!(weights(deps.as_ref(), &env), (6, 6));
+    }
+
+    #[test]
+    fn instantiate_validation() {
+        let mut deps = mock_dependencies_with_balance(&[]);
+        let msg = |incremental_donation, collective_ratio| InstantiateMsg {
+            owner: "owner".to_string(),
+            incremental_donation,
+            collective_ratio,
+            other_denoms: DenomPolicy::PassThrough,
+            decay: Decay::None,
+        };
+
+        let err = instantiate(deps.as_mut(), mock_env(), mock_info("manager", &[]), msg(coin(100, "utgd"), Decimal::percent(101)))
+            .unwrap_err();
+        assert_eq!(err, ContractError::InvalidRatio {});
+
+        let err = instantiate(deps.as_mut(), mock_env(), mock_info("manager", &[]), msg(coin(0, "utgd"), Decimal::percent(60)))
+            .unwrap_err();
+        assert_eq!(err, ContractError::InvalidIncrement {});
+
+        let err = instantiate(deps.as_mut(), mock_env(), mock_info("manager", &[]), msg(coin(100, ""), Decimal::percent(60)))
+            .unwrap_err();
+        assert_eq!(err, ContractError::InvalidIncrement {});
+
+        let mut allowlist = msg(coin(100, "utgd"), Decimal::percent(60));
+        allowlist.other_denoms = DenomPolicy::Allowlist(vec![coin(10, "uatom"), coin(100, "utgd")]);
+        let err = instantiate(deps.as_mut(), mock_env(), mock_info("manager", &[]), allowlist.clone()).unwrap_err();
+        assert_eq!(err, ContractError::DuplicateDenom { denom: "utgd".to_string() });
+
+        allowlist.other_denoms = DenomPolicy::Allowlist(vec![coin(0, "uatom")]);
+        let err = instantiate(deps.as_mut(), mock_env(), mock_info("manager", &[]), allowlist).unwrap_err();
+        assert_eq!(err, ContractError::InvalidIncrement {});
+
+        // Both bounds of the ratio are fine
+        instantiate(deps.as_mut(), mock_env(), mock_info("manager", &[]), msg(coin(100, "utgd"), Decimal::one())).unwrap();
+        instantiate(deps.as_mut(), mock_env(), mock_info("manager", &[]), msg(coin(100, "utgd"), Decimal::zero())).unwrap();
+    }
+
+    #[test]
+    fn migrate_checks_version() {
+        let mut deps = setup(&[]);
+        assert_eq!(get_contract_version(&deps.storage).unwrap().version, CONTRACT_VERSION);
+
+        // Older versions are brought up to date
+        set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "0.0.1").unwrap();
+        migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+        assert_eq!(get_contract_version(&deps.storage).unwrap().version, CONTRACT_VERSION);
+
+        set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "99.0.0").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+        assert_eq!(err, ContractError::Downgrade { from: "99.0.0".to_string(), to: CONTRACT_VERSION.to_string() });
+
+        set_contract_version(deps.as_mut().storage, "crates.io:cw20-base", "0.0.1").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+        assert_eq!(err, ContractError::WrongContract {
+            expected: CONTRACT_NAME.to_string(),
+            found: "crates.io:cw20-base".to_string(),
+        });
+    }
+
+    #[test]
+    fn migrate_converts_legacy_peers() {
+        let mut deps = mock_dependencies_with_balance(&[]);
+        let legacy = LegacyState {
+            donators: 3,
+            incremental_donation: coin(10, "utgd"),
+            collective_ratio: Decimal::percent(60),
+            manager: Addr::unchecked("manager"),
+        };
+        LEGACY_STATE.save(deps.as_mut().storage, &legacy).unwrap();
+
+        // Without a recorded version the peer is taken to be a legacy one
+        let resp = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+        assert!(resp.attributes.iter().any(|attr| attr.key == "from_version" && attr.value == "legacy"));
+        assert_eq!(get_contract_version(&deps.storage).unwrap().version, CONTRACT_VERSION);
+        assert_eq!(STATE.load(&deps.storage).unwrap(), State {
+            donators: 3,
+            unique_donors: 0,
+            incremental_donation: coin(10, "utgd"),
+            collective_ratio: Decimal::percent(60),
+            other_denoms: DenomPolicy::PassThrough,
+            manager: Addr::unchecked("manager"),
+            detached: false,
+            decay: Decay::None,
+        });
+    }
+}
@@ -0,0 +1,171 @@
+use cosmwasm_std::{BlockInfo, Decimal256, Order, StdResult, Storage, Uint128};
+use cw_storage_plus::{Item, Map};
+use cw_utils::Duration;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::state::Decay;
+
+// With decay, `State.donators` still counts every increment ever donated, while the weight reported to the
+// manager fades. Nothing is iterated per block. Exponential decay scales the whole total at once, as every
+// donation loses the same share of its weight over the same time. With the linear one every donation stops
+// counting at a known point, where it is dropped from the total on the next update.
+// Points are block heights or seconds, whatever the half life is given in.
+
+// ln(2), to turn halvings into a power of e
+const LN_2: Decimal256 = Decimal256::raw(693_147_180_559_945_309);
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct Decayed {
+    pub weight: Decimal256,
+    // Point the weight was computed at
+    pub at: u64,
+}
+
+// Donations with linear decay. The weight at `now` is `units - (now * units - unit_points) / lifetime`
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct Alive {
+    pub units: Decimal256,
+    // Sum of the units of every donation times the point it was made at
+    pub unit_points: Decimal256,
+}
+
+pub const DECAYED: Item<Decayed> = Item::new("decayed");
+pub const ALIVE: Item<Alive> = Item::new("alive");
+// point the donations stop counting at -> donations made `lifetime` before it
+pub const EXPIRING: Map<u64, Alive> = Map::new("expiring");
+
+// Current point and half life
+fn points(half_life: &Duration, block: &BlockInfo) -> (u64, u64) {
+    match half_life {
+        Duration::Height(blocks) => (block.height, *blocks),
+        Duration::Time(seconds) => (block.time.seconds(), *seconds),
+    }
+}
+
+// Weight of the peer right now
+pub fn weight(storage: &dyn Storage, decay: &Decay, donators: u64, block: &BlockInfo) -> StdResult<Decimal256> {
+    match decay {
+        Decay::None => Ok(Decimal256::from_ratio(donators, 1u8)),
+        Decay::Exponential { half_life } => {
+            let (now, half_life) = points(half_life, block);
+            let decayed = DECAYED.may_load(storage)?.unwrap_or_default();
+            Ok(decayed.weight * half_power(now.saturating_sub(decayed.at), half_life))
+        }
+        Decay::Linear { half_life } => {
+            let (now, half_life) = points(half_life, block);
+            let mut alive = ALIVE.may_load(storage)?.unwrap_or_default();
+            for expired in EXPIRING.range(storage, None, None, Order::Ascending) {
+                let (point, expired) = expired?;
+                if point > now {
+                    break;
+                }
+                alive.units -= expired.units;
+                alive.unit_points -= expired.unit_points;
+            }
+            Ok(linear_weight(&alive, now, half_life))
+        }
+    }
+}
+
+// The weight as the manager gets it, without the fraction
+pub fn reported(weight: Decimal256) -> StdResult<u64> {
+    Ok(Uint128::try_from(weight.to_uint_floor())?.u128() as u64)
+}
+
+// Adds the units of a new donation
+pub fn add(storage: &mut dyn Storage, decay: &Decay, block: &BlockInfo, units: u64) -> StdResult<()> {
+    let units = Decimal256::from_ratio(units, 1u8);
+    match decay {
+        Decay::None => Ok(()),
+        Decay::Exponential { half_life } => {
+            let weight = weight(storage, decay, 0, block)? + units;
+            let (at, _) = points(half_life, block);
+            DECAYED.save(storage, &Decayed { weight, at })
+        }
+        Decay::Linear { half_life } => {
+            let (now, half_life) = points(half_life, block);
+            add_alive(storage, now, half_life, units)
+        }
+    }
+}
+
+// Starts tracking `weight` under a new decay, as if it was all donated right now
+pub fn reset(storage: &mut dyn Storage, decay: &Decay, block: &BlockInfo, weight: Decimal256) -> StdResult<()> {
+    DECAYED.remove(storage);
+    ALIVE.remove(storage);
+    let expiring: Vec<_> = EXPIRING
+        .keys(storage, None, None, Order::Ascending)
+        .collect::<StdResult<_>>()?;
+    for point in expiring {
+        EXPIRING.remove(storage, point);
+    }
+
+    match decay {
+        Decay::None => Ok(()),
+        Decay::Exponential { half_life } => {
+            let (at, _) = points(half_life, block);
+            DECAYED.save(storage, &Decayed { weight, at })
+        }
+        Decay::Linear { half_life } => {
+            let (now, half_life) = points(half_life, block);
+            add_alive(storage, now, half_life, weight)
+        }
+    }
+}
+
+// Drops the expired donations, then adds `units` donated at `now`
+fn add_alive(storage: &mut dyn Storage, now: u64, half_life: u64, units: Decimal256) -> StdResult<()> {
+    let mut alive = ALIVE.may_load(storage)?.unwrap_or_default();
+    let expired = EXPIRING
+        .range(storage, None, None, Order::Ascending)
+        .take_while(|expired| expired.as_ref().map_or(true, |(point, _)| *point <= now))
+        .collect::<StdResult<Vec<_>>>()?;
+    for (point, expired) in expired {
+        alive.units -= expired.units;
+        alive.unit_points -= expired.unit_points;
+        EXPIRING.remove(storage, point);
+    }
+
+    let unit_points = units * Decimal256::from_ratio(now, 1u8);
+    alive.units += units;
+    alive.unit_points += unit_points;
+    ALIVE.save(storage, &alive)?;
+    EXPIRING.update(storage, now + 2 * half_life, |expiring| -> StdResult<_> {
+        let mut expiring = expiring.unwrap_or_default();
+        expiring.units += units;
+        expiring.unit_points += unit_points;
+        Ok(expiring)
+    })?;
+    Ok(())
+}
+
+// Every donation loses its weight evenly over two half lives
+fn linear_weight(alive: &Alive, now: u64, half_life: u64) -> Decimal256 {
+    let lifetime = Decimal256::from_ratio(2 * half_life, 1u8);
+    let aged = alive.units * Decimal256::from_ratio(now, 1u8) - alive.unit_points;
+    alive.units.saturating_sub(aged / lifetime)
+}
+
+// 0.5^(elapsed / half_life). Whole halvings are exact, the remaining fraction `f` is 1 / e^(f * ln 2)
+// with e^x summed as its Taylor series, which converges quickly as x < ln 2.
+fn half_power(elapsed: u64, half_life: u64) -> Decimal256 {
+    let halvings = elapsed / half_life;
+    // Below the precision of a decimal by then
+    if halvings >= 64 {
+        return Decimal256::zero();
+    }
+    let whole = Decimal256::percent(50).pow(halvings as u32);
+
+    let x = Decimal256::from_ratio(elapsed % half_life, half_life) * LN_2;
+    let mut exp = Decimal256::one();
+    let mut term = Decimal256::one();
+    for k in 1u64.. {
+        term = term * x / Decimal256::from_ratio(k, 1u8);
+        if term.is_zero() {
+            break;
+        }
+        exp += term;
+    }
+    whole / exp
+}
@@ -1,11 +1,80 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{DivideByZeroError, OverflowError, StdError};
+use cw_utils::PaymentError;
 use thiserror::Error;
 
-#[derive(Error, Debug)]
+use crate::helpers::MigrateError;
+use crate::state::IncrementError;
+
+#[derive(Error, Debug, PartialEq)]
 pub enum ContractError {
     #[error("{0}")]
     Std(#[from] StdError),
 
-    #[error("Custom Error val: {val:?}")]
-    CustomError { val: String },
+    #[error("{0}")]
+    Overflow(#[from] OverflowError),
+
+    #[error("{0}")]
+    DivideByZero(#[from] DivideByZeroError),
+
+    #[error("{0}")]
+    Payment(#[from] PaymentError),
+
+    #[error("No funds sent with the donation")]
+    NoFunds {},
+
+    #[error("Donations in {denom} are not accepted")]
+    InvalidDenom { denom: String },
+
+    #[error("Collective ratio has to be between 0 and 1")]
+    InvalidRatio {},
+
+    #[error("Incremental donation has to be a non-zero amount of a denom")]
+    InvalidIncrement {},
+
+    #[error("Denom {denom} is listed more than once")]
+    DuplicateDenom { denom: String },
+
+    #[error("Memo cannot be longer than {max} characters")]
+    MemoTooLong { max: usize },
+
+    #[error("Half life has to be above 0 and at most 100 years")]
+    InvalidHalfLife {},
+
+    #[error("The peer is detached from the collective")]
+    Detached {},
+
+    #[error("Unauthorized")]
+    Unauthorized {},
+
+    #[error("Nothing to withdraw")]
+    NothingToWithdraw {},
+
+    #[error("Invalid contract version {version}")]
+    InvalidVersion { version: String },
+
+    #[error("Cannot migrate from {found} contract, expected {expected}")]
+    WrongContract { expected: String, found: String },
+
+    #[error("Cannot downgrade from version {from} to {to}")]
+    Downgrade { from: String, to: String },
+}
+
+impl From<IncrementError> for ContractError {
+    fn from(err: IncrementError) -> Self {
+        match err {
+            IncrementError::Invalid => ContractError::InvalidIncrement {},
+            IncrementError::Duplicate(denom) => ContractError::DuplicateDenom { denom },
+        }
+    }
+}
+
+impl From<MigrateError> for ContractError {
+    fn from(err: MigrateError) -> Self {
+        match err {
+            MigrateError::Std(err) => ContractError::Std(err),
+            MigrateError::InvalidVersion(version) => ContractError::InvalidVersion { version },
+            MigrateError::WrongContract { expected, found } => ContractError::WrongContract { expected, found },
+            MigrateError::Downgrade { from, to } => ContractError::Downgrade { from, to },
+        }
+    }
 }
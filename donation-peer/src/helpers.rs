@@ -0,0 +1,78 @@
+use cosmwasm_std::{BankMsg, Coin, CosmosMsg, StdError, StdResult, Storage, to_json_binary, WasmMsg};
+use cw2::CONTRACT;
+use cw20::Cw20ExecuteMsg;
+use semver::Version;
+
+// CW20 tokens are accounted for like native coins, under the address of the token contract
+// with this prefix as their denom
+pub const CW20_PREFIX: &str = "cw20:";
+
+pub fn cw20_denom(token: &str) -> String {
+    format!("{}{}", CW20_PREFIX, token)
+}
+
+// Address of the token contract if `denom` is a CW20 one
+pub fn cw20_token(denom: &str) -> Option<&str> {
+    denom.strip_prefix(CW20_PREFIX)
+}
+
+// Sends `coins` to `recipient`: native coins with a single bank send, every CW20 token with its own transfer
+pub fn send_coins(recipient: &str, coins: Vec<Coin>) -> StdResult<Vec<CosmosMsg>> {
+    let mut native = vec![];
+    let mut msgs = vec![];
+    for coin in coins {
+        match cw20_token(&coin.denom) {
+            Some(token) => {
+                let msg = WasmMsg::Execute {
+                    contract_addr: token.to_string(),
+                    msg: to_json_binary(&Cw20ExecuteMsg::Transfer { recipient: recipient.to_string(), amount: coin.amount })?,
+                    funds: vec![],
+                };
+                msgs.push(msg.into());
+            }
+            None => native.push(coin),
+        }
+    }
+
+    if !native.is_empty() {
+        msgs.insert(0, BankMsg::Send { to_address: recipient.to_string(), amount: native }.into());
+    }
+    Ok(msgs)
+}
+
+// Why a contract cannot be migrated, turned into the error of the contract
+#[derive(Debug, PartialEq)]
+pub enum MigrateError {
+    Std(StdError),
+    InvalidVersion(String),
+    WrongContract { expected: String, found: String },
+    Downgrade { from: String, to: String },
+}
+
+impl From<StdError> for MigrateError {
+    fn from(err: StdError) -> Self {
+        MigrateError::Std(err)
+    }
+}
+
+// Version the contract `name` is migrated from to `version`, None for a deployment from before the
+// versions were recorded with cw2. Every version changing the storage layout adds its step to the
+// `migrate_state` of its contract, guarded by `from < Version::new(..)`, so a contract can skip several
+// versions in one migration.
+pub fn migrating_from(storage: &dyn Storage, name: &str, version: &str) -> Result<Option<Version>, MigrateError> {
+    let Some(stored) = CONTRACT.may_load(storage)? else {
+        return Ok(None);
+    };
+    if stored.contract != name {
+        return Err(MigrateError::WrongContract { expected: name.to_string(), found: stored.contract });
+    }
+
+    let parse = |version: &str| -> Result<Version, MigrateError> {
+        version.parse().map_err(|_| MigrateError::InvalidVersion(version.to_string()))
+    };
+    let from = parse(&stored.version)?;
+    if from > parse(version)? {
+        return Err(MigrateError::Downgrade { from: stored.version, to: version.to_string() });
+    }
+    Ok(Some(from))
+}
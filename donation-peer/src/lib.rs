@@ -1,6 +1,7 @@
 pub mod contract;
+pub mod decay;
 mod error;
-//pub mod helpers;
+pub mod helpers;
 pub mod msg;
 pub mod state;
 
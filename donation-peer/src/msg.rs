@@ -1,20 +1,72 @@
 use cosmwasm_std::{Addr, Coin, Decimal};
+use cw20::Cw20ReceiveMsg;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::state::{Decay, DenomPolicy, Donation, DonorRecord};
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct InstantiateMsg {
     pub owner: String,
     pub incremental_donation: Coin,
-    pub collective_ratio: Decimal
+    pub collective_ratio: Decimal,
+    pub other_denoms: DenomPolicy,
+    #[serde(default)]
+    pub decay: Decay,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
+    // `memo` is shown with the donation, `anonymous` donations do not show the donor
+    Donate {
+        memo: Option<String>,
+        anonymous: Option<bool>,
+    },
+    // Donation in an allowlisted CW20 token, the hook message is a `ReceiveMsg`
+    Receive(Cw20ReceiveMsg),
+    // Owner only. Withdraws `amount` (whole balance when not set) to `receiver` (owner when not set)
+    Withdraw {
+        amount: Option<Vec<Coin>>,
+        receiver: Option<String>,
+    },
+    // Manager only. Sent when the owner leaves the collective
+    Detach {},
+    // Manager only. Replaces the collective settings of the peer
+    UpdateConfig {
+        incremental_donation: Coin,
+        collective_ratio: Decimal,
+        other_denoms: DenomPolicy,
+        #[serde(default)]
+        decay: Decay,
+    },
+    // Reports the current weight to the manager. With decay it drops between donations, anyone can call it
+    SyncWeight {},
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveMsg {
+    Donate {
+        memo: Option<String>,
+        anonymous: Option<bool>,
+    },
+}
+
+// Messages of the donation-manager this contract sends. They are mirrored here because the
+// manager crate depends on the peer crate and not the other way around.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ManagerExecMsg {
     Donate {},
-    Withdraw {},
+    UpdateWeight {
+        weight: u64,
+        #[serde(default)]
+        unique_donors: u64,
+    },
+    // Whole donation received by the peer, matched by the quadratic funding round of the manager if any
+    RecordDonation { donor: String, funds: Vec<Coin> },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -23,12 +75,28 @@ pub enum QueryMsg {
     Donators {},
     Owner {},
     Manager {},
+    Donor { addr: String },
+    // Donors ordered by address
+    Donors {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    // Donations from the latest one back, `start_after` is the id of the last donation of the previous page
+    RecentDonations {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct DonatorsResponse {
+    // Increments donated, "units"
     pub donators: u64,
+    // Donors with at least one increment, "supporters"
+    pub unique_donors: u64,
+    // What the peer weighs in the collective right now, lower than `donators` with decay
+    pub weight: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -41,8 +109,41 @@ pub struct OwnerResp {
 #[serde(rename_all = "snake_case")]
 pub struct ManagerResp {
     pub manager: Addr,
+    pub detached: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct DonorInfo {
+    pub addr: Addr,
+    pub record: DonorRecord,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct DonorResponse {
+    pub donor: Option<DonorInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct DonorsResponse {
+    pub donors: Vec<DonorInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct DonationInfo {
+    pub id: u64,
+    pub donation: Donation,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct RecentDonationsResponse {
+    pub donations: Vec<DonationInfo>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
-pub enum MigrateMsg {}
+pub struct MigrateMsg {}
@@ -1,17 +1,166 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Addr, Coin, Decimal};
-use cw_storage_plus::Item;
+use cosmwasm_std::{Addr, Coin, Decimal, Timestamp, Uint128};
+use cw_storage_plus::{Item, Map};
+use cw_utils::Duration;
+
+use crate::helpers::cw20_token;
+
+// What happens to donated coins which are not in the `incremental_donation` denom
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DenomPolicy {
+    // The whole donation fails
+    Reject,
+    // Coins are accepted and split as usual, but they never count toward `donators`.
+    // CW20 tokens are not, anyone can call Receive claiming a donation of a worthless token
+    PassThrough,
+    // Only the listed denoms are accepted. Each of them counts toward `donators` like the
+    // `incremental_donation` denom does, with the listed amount as its increment
+    Allowlist(Vec<Coin>),
+}
+
+impl DenomPolicy {
+    // Whether coins in `denom`, other than the `incremental_donation` one, can be donated
+    pub fn accepts(&self, denom: &str) -> bool {
+        match self {
+            DenomPolicy::Reject => false,
+            DenomPolicy::PassThrough => cw20_token(denom).is_none(),
+            DenomPolicy::Allowlist(increments) => increments.iter().any(|coin| coin.denom == denom),
+        }
+    }
+
+    // Increments of the denoms which add weight besides the `incremental_donation` one
+    pub fn increments(&self) -> &[Coin] {
+        match self {
+            DenomPolicy::Allowlist(increments) => increments,
+            _ => &[],
+        }
+    }
+
+    // Every increment, `incremental_donation` included, has to be above zero and in a denom of its own.
+    // The manager checks its config with this too, so the peers never reject a propagated one
+    pub fn validate_increments(&self, incremental_donation: &Coin) -> Result<(), IncrementError> {
+        let increments: Vec<_> = std::iter::once(incremental_donation)
+            .chain(self.increments())
+            .collect();
+        for (idx, increment) in increments.iter().enumerate() {
+            if increment.amount.is_zero() || increment.denom.is_empty() {
+                return Err(IncrementError::Invalid);
+            }
+            if increments[..idx].iter().any(|other| other.denom == increment.denom) {
+                return Err(IncrementError::Duplicate(increment.denom.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Why the increments of a denom policy are not valid
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IncrementError {
+    Invalid,
+    Duplicate(String),
+}
+
+// How the weight of donations fades over time, with the half life in blocks or seconds
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Decay {
+    // Donations count forever
+    #[default]
+    None,
+    // Donations lose half of their weight every half life
+    Exponential { half_life: Duration },
+    // Donations lose their weight evenly, and stop counting after two half lives
+    Linear { half_life: Duration },
+}
+
+// 100 years in seconds, and longer than that in blocks. Keeps the points donations expire at within a u64
+pub const MAX_HALF_LIFE: u64 = 100 * 365 * 24 * 60 * 60;
+
+impl Decay {
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Decay::None => true,
+            Decay::Exponential { half_life } | Decay::Linear { half_life } => match half_life {
+                Duration::Height(points) | Duration::Time(points) => (1..=MAX_HALF_LIFE).contains(points),
+            },
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct State {
+    // Increments donated so far. With decay the weight of the peer is lower, see `decay`
     pub donators: u64,
+    // Donors who donated at least one increment, dust alone does not make a supporter
+    #[serde(default)]
+    pub unique_donors: u64,
     pub incremental_donation: Coin,
     pub collective_ratio: Decimal,
-    pub manager: Addr           //Manager is the donation-manager
+    pub other_denoms: DenomPolicy,
+    pub manager: Addr,          //Manager is the donation-manager
+    // Set once the owner left the collective. Detached peers keep whole donations.
+    pub detached: bool,
+    #[serde(default)]
+    pub decay: Decay,
 }
 
 pub const STATE: Item<State> = Item::new("state");
 pub const OWNER: Item<Addr> = Item::new("owner");
+
+// State of the peers deployed before the versions were recorded with cw2, only read to migrate them
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct LegacyState {
+    pub donators: u64,
+    pub incremental_donation: Coin,
+    pub collective_ratio: Decimal,
+    pub manager: Addr,
+}
+
+pub const LEGACY_STATE: Item<LegacyState> = Item::new("state");
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct DonorRecord {
+    // Cumulative donations, one entry per denom
+    pub donated: Vec<Coin>,
+    pub first_height: u64,
+    pub last_height: u64,
+    // How much this donator adds to `State.donators`
+    pub weight: u64,
+}
+
+impl DonorRecord {
+    pub fn donated_in(&self, denom: &str) -> Uint128 {
+        self.donated
+            .iter()
+            .find(|coin| coin.denom == denom)
+            .map(|coin| coin.amount)
+            .unwrap_or_default()
+    }
+}
+
+// donator address -> donations history
+pub const DONORS: Map<&Addr, DonorRecord> = Map::new("donors");
+// All anonymous donations added up as if they came from a single donor, so none of them is tied to an address
+pub const ANONYMOUS: Item<DonorRecord> = Item::new("anonymous");
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Donation {
+    // Not stored for anonymous donations
+    pub donor: Option<Addr>,
+    pub amount: Vec<Coin>,
+    pub memo: Option<String>,
+    pub height: u64,
+    pub time: Timestamp,
+}
+
+// donation id -> donation. Ids grow with every donation, so the latest ones come last
+pub const DONATIONS: Map<u64, Donation> = Map::new("donations");
+pub const NEXT_DONATION_ID: Item<u64> = Item::new("next_donation_id");